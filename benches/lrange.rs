@@ -0,0 +1,54 @@
+//! Decode a 10k element LRANGE reply with the owned and the borrowed parser.
+//!
+//! Run with `cargo bench --bench lrange`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use bytes::{Bytes, BytesMut};
+use parser_toy::nom::redis::resp::Resp;
+use parser_toy::nom::redis::resp_ref::RespRef;
+
+const ELEMENTS: usize = 10_000;
+const ITERATIONS: u32 = 200;
+
+fn lrange_reply() -> BytesMut {
+    let mut src = BytesMut::new();
+    src.extend_from_slice(format!("*{}\r\n", ELEMENTS).as_bytes());
+    for i in 0..ELEMENTS {
+        let value = format!("value:{:08}", i);
+        src.extend_from_slice(format!("${}\r\n{}\r\n", value.len(), value).as_bytes());
+    }
+    src
+}
+
+// bench 预热后运行 ITERATIONS 次，输出每次的平均耗时
+fn bench(name: &str, mut f: impl FnMut()) -> Duration {
+    for _ in 0..ITERATIONS / 10 {
+        f();
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let per_iter = start.elapsed() / ITERATIONS;
+    println!("{:<28} {:>10.1?}/iter", name, per_iter);
+    per_iter
+}
+
+fn main() {
+    let src = lrange_reply();
+    let frame: Bytes = src.clone().freeze();
+    println!("LRANGE reply: {} elements, {} bytes", ELEMENTS, src.len());
+
+    let owned = bench("Resp::from_resp", || {
+        black_box(Resp::from_resp(black_box(&src)).unwrap());
+    });
+    let borrowed = bench("RespRef::from_frame", || {
+        black_box(RespRef::from_frame(black_box(&frame)).unwrap());
+    });
+    bench("RespRef -> Resp (shared)", || {
+        black_box(RespRef::from_frame(black_box(&frame)).unwrap().to_resp_in(&frame));
+    });
+    println!("borrowed speedup: {:.2}x", owned.as_secs_f64() / borrowed.as_secs_f64());
+}
@@ -0,0 +1,160 @@
+use bytes::Bytes;
+use nom::branch::alt;
+use nom::bytes::complete::{is_not, tag, take_while, take_while_m_n};
+use nom::character::complete::char;
+use nom::combinator::{map, value};
+use nom::multi::fold_many0;
+use nom::number::complete::u8 as any_byte;
+use nom::sequence::{delimited, preceded};
+
+use crate::nom::redis::error::{RespError, RespErrorKind, RespParseError};
+use crate::nom::redis::resp::{self, RespResult};
+
+/// Split a command line into its argv, with the same rules as redis-cli
+/// (`sdssplitargs`):
+///
+/// - arguments are separated by whitespace, but an unquoted argument only
+///   ends on a space, `\t`, `\r`, `\n` or `\0` (`\v` and `\f` are kept);
+/// - `"..."` supports `\xHH`, `\n`, `\r`, `\t`, `\b` and `\a`, any other
+///   escaped character stands for itself;
+/// - `'...'` only supports `\'`;
+/// - a closing quote must be followed by whitespace or the end of the line.
+///
+/// A blank line gives an empty argv.
+pub fn split_args(line: &[u8]) -> Result<Vec<Bytes>, RespError> {
+    parse_args(line).map(|(_, argv)| argv).map_err(|e| RespParseError::into_error(e, line))
+}
+
+/// nom parser for [`split_args`], consuming the whole input.
+pub fn parse_args(mut i: &[u8]) -> RespResult<'_, Vec<Bytes>> {
+    let mut argv = Vec::new();
+    loop {
+        let (remain, _) = take_while(is_space)(i)?;
+        if remain.is_empty() {
+            return Ok((remain, argv));
+        }
+        let (remain, arg) = arg(remain)?;
+        argv.push(Bytes::from(arg));
+        i = remain;
+    }
+}
+
+// is_space 与 C 的 isspace 相同，包含 \v 和 \f；
+// sdssplitargs 读到 \0 就结束了，这里把 \0 也当作参数之间的空白
+fn is_space(c: u8) -> bool {
+    c == b' ' || c == b'\0' || (b'\t'..=b'\r').contains(&c)
+}
+
+// is_separator 结束不带引号的参数，与 sdssplitargs 相同，不包含 \v 和 \f
+fn is_separator(c: u8) -> bool {
+    matches!(c, b' ' | b'\n' | b'\r' | b'\t' | b'\0')
+}
+
+// Piece 引号内的一段内容：原样的文本或转义得到的字节
+#[derive(Clone)]
+enum Piece<'a> {
+    Text(&'a [u8]),
+    Byte(u8),
+}
+
+// arg 一个参数：不含引号的文本，可以紧跟一段引号内容，引号之后参数结束
+fn arg(i: &[u8]) -> RespResult<'_, Vec<u8>> {
+    let (remain, mut arg) = map(take_while(|c| !is_separator(c) && c != b'"' && c != b'\''), <[u8]>::to_vec)(i)?;
+    let quoted = match remain.first() {
+        Some(b'"') => double_quoted(remain),
+        Some(b'\'') => single_quoted(remain),
+        _ => return Ok((remain, arg)),
+    };
+    let (remain, pieces) = quoted.or_else(|_| resp::fail(i, RespErrorKind::UnbalancedQuotes))?;
+    if remain.first().is_some_and(|&c| !is_space(c)) {
+        return resp::fail(i, RespErrorKind::UnbalancedQuotes);
+    }
+    for piece in pieces {
+        match piece {
+            Piece::Text(text) => arg.extend_from_slice(text),
+            Piece::Byte(byte) => arg.push(byte),
+        }
+    }
+    Ok((remain, arg))
+}
+
+// double_quoted 双引号内容，\xHH 需要两位十六进制数字，否则按普通转义处理
+fn double_quoted(i: &[u8]) -> RespResult<'_, Vec<Piece<'_>>> {
+    let hex = map(take_while_m_n(2, 2, |c: u8| c.is_ascii_hexdigit()), |hex: &[u8]| {
+        hex.iter().fold(0, |byte, &c| byte << 4 | (c as char).to_digit(16).unwrap_or_default() as u8)
+    });
+    let escaped = map(any_byte, |c| match c {
+        b'n' => b'\n',
+        b'r' => b'\r',
+        b't' => b'\t',
+        b'b' => 0x08,
+        b'a' => 0x07,
+        c => c,
+    });
+    delimited(
+        char('"'),
+        pieces(alt((
+            map(is_not("\\\""), Piece::Text),
+            map(preceded(tag("\\x"), hex), Piece::Byte),
+            map(preceded(char('\\'), escaped), Piece::Byte),
+        ))),
+        char('"'),
+    )(i)
+}
+
+// single_quoted 单引号内容，只有 \' 是转义，其余反斜杠原样保留
+fn single_quoted(i: &[u8]) -> RespResult<'_, Vec<Piece<'_>>> {
+    delimited(
+        char('\''),
+        pieces(alt((
+            map(is_not("\\'"), Piece::Text),
+            value(Piece::Byte(b'\''), tag("\\'")),
+            value(Piece::Byte(b'\\'), char('\\')),
+        ))),
+        char('\''),
+    )(i)
+}
+
+fn pieces<'a, F>(piece: F) -> impl FnMut(&'a [u8]) -> RespResult<'a, Vec<Piece<'a>>>
+where
+    F: nom::Parser<&'a [u8], Piece<'a>, RespParseError<'a>>,
+{
+    fold_many0(piece, Vec::new, |mut pieces, piece| {
+        pieces.push(piece);
+        pieces
+    })
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::nom::redis::args::*;
+
+    fn argv(args: &[&[u8]]) -> Vec<Bytes> {
+        args.iter().map(|arg| Bytes::copy_from_slice(arg)).collect()
+    }
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(
+            split_args(br#"SET "my key" 'it\'s' "\x00\n""#),
+            Ok(argv(&[b"SET", b"my key", b"it's", b"\x00\n"]))
+        );
+        assert_eq!(split_args(br#"SET "my key" "\x00\n""#), Ok(argv(&[b"SET", b"my key", b"\x00\n"])));
+        assert_eq!(split_args(b"  GET\tk \x0b "), Ok(argv(&[b"GET", b"k"])));
+        assert_eq!(split_args(b" \t "), Ok(argv(&[])));
+        assert_eq!(split_args(b"a\x0bb\x0cc d\0e"), Ok(argv(&[b"a\x0bb\x0cc", b"d", b"e"])));
+        assert_eq!(split_args(br#"a"b c" "" ''"#), Ok(argv(&[b"ab c", b"", b""])));
+        assert_eq!(split_args(br#""\xff\xg\q\"\\" '\x\'\"'"#), Ok(argv(&[b"\xffxgq\"\\", br#"\x'\""#])));
+    }
+
+    #[test]
+    fn test_split_args_errors() {
+        let unbalanced = |offset| Err(RespError { kind: RespErrorKind::UnbalancedQuotes, offset });
+        assert_eq!(split_args(br#"SET k "v"#), unbalanced(6));
+        assert_eq!(split_args(br#"SET k 'v"#), unbalanced(6));
+        assert_eq!(split_args(br#"SET k "v"x"#), unbalanced(6));
+        // 与 redis-cli 的 sdssplitargs 一致，闭合引号后必须是空白，因此 'it''s' 不是合法的参数
+        assert_eq!(split_args(br#"SET k 'it''s'"#), unbalanced(6));
+    }
+}
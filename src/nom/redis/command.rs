@@ -1,37 +1,149 @@
+use std::fmt::Write;
 use std::string::String;
 
-use bytes::{BufMut, BytesMut};
+use bytes::{BufMut, Bytes, BytesMut};
+use structopt::clap::{Arg, ArgGroup, ArgMatches};
 use structopt::StructOpt;
 
-#[derive(Debug, Clone)]
-struct CmdBuilder {
-    args: Vec<String>,
+use crate::nom::redis::args::split_args;
+use crate::nom::redis::error::RespError;
+
+/// Value that can be written as one argument of a command.
+///
+/// Implemented for strings and byte slices, which are sent as is, and for
+/// integers and floats, which are sent in their decimal form.
+pub trait ToArg {
+    /// Append the argument as a bulk string, `$<len>\r\n<bytes>\r\n`.
+    fn write_arg(&self, out: &mut BytesMut);
+}
+
+// write_bulk 写入一个 bulk string 参数
+fn write_bulk(out: &mut BytesMut, arg: &[u8]) {
+    out.put_u8(b'$');
+    write_decimal(out, arg.len());
+    out.put_slice(arg);
+    out.put_slice(b"\r\n");
+}
+
+// write_decimal 写入数字及 \r\n
+fn write_decimal(out: &mut BytesMut, n: usize) {
+    out.put_slice(Digits::format(n).as_bytes());
+    out.put_slice(b"\r\n");
+}
+
+// Digits 将数字格式化到栈上的缓冲区，不分配 String
+struct Digits {
+    buf: [u8; 32],
+    len: usize,
+}
+
+impl Digits {
+    fn format<T: std::fmt::Debug>(n: T) -> Self {
+        let mut digits = Digits { buf: [0; 32], len: 0 };
+        // f64 的 Debug 格式在很大或很小时使用科学计数法，最长 24 个字符
+        let _ = write!(digits, "{:?}", n);
+        digits
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl Write for Digits {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        let end = self.len + s.len();
+        self.buf.get_mut(self.len..end).ok_or(std::fmt::Error)?.copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+macro_rules! bytes_arg {
+    ($($ty:ty),+) => {
+        $(impl ToArg for $ty {
+            fn write_arg(&self, out: &mut BytesMut) {
+                write_bulk(out, self.as_ref());
+            }
+        })+
+    };
+}
+
+bytes_arg!(str, String, [u8], Vec<u8>, Bytes);
+
+impl<const N: usize> ToArg for [u8; N] {
+    fn write_arg(&self, out: &mut BytesMut) {
+        write_bulk(out, self);
+    }
+}
+
+macro_rules! number_arg {
+    ($($ty:ty),+) => {
+        $(impl ToArg for $ty {
+            fn write_arg(&self, out: &mut BytesMut) {
+                write_bulk(out, Digits::format(self).as_bytes());
+            }
+        })+
+    };
+}
+
+number_arg!(i8, u8, i16, u16, i32, u32, i64, u64, isize, usize, f32, f64);
+
+impl<T: ToArg + ?Sized> ToArg for &T {
+    fn write_arg(&self, out: &mut BytesMut) {
+        (**self).write_arg(out)
+    }
+}
+
+/// Builder for a command in the multibulk form sent by clients.
+///
+/// Arguments are encoded into one buffer as they are added, e.g.
+/// `CmdBuilder::new().arg("EXPIRE").arg(b"key\x00").arg(10)`.
+#[derive(Debug, Clone, Default)]
+pub struct CmdBuilder {
+    args: BytesMut,
+    len: usize,
 }
 
 impl CmdBuilder {
-    fn new() -> Self {
-        CmdBuilder { args: vec![] }
+    pub fn new() -> Self {
+        CmdBuilder::default()
+    }
+
+    /// Build a command from a line typed like in redis-cli, see [`split_args`].
+    pub fn from_line(line: &str) -> Result<Self, RespError> {
+        let mut builder = CmdBuilder::new();
+        split_args(line.as_bytes())?.iter().for_each(|arg| builder.add_arg(arg));
+        Ok(builder)
     }
 
-    fn arg(mut self, arg: &str) -> Self {
-        self.args.push(format!("${}", arg.len()));
-        self.args.push(arg.to_string());
+    pub fn arg<T: ToArg>(mut self, arg: T) -> Self {
+        self.add_arg(arg);
         self
     }
 
-    fn add_arg(&mut self, arg: &str) {
-        self.args.push(format!("${}", arg.len()));
-        self.args.push(arg.to_string());
+    pub fn add_arg<T: ToArg>(&mut self, arg: T) {
+        arg.write_arg(&mut self.args);
+        self.len += 1;
+    }
+
+    /// Number of arguments, including the command name.
+    pub fn len(&self) -> usize {
+        self.len
     }
 
-    fn to_bytes(&self) -> BytesMut {
-        let mut bytes = BytesMut::new();
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn to_bytes(&self) -> BytesMut {
         // example
-        // ping: *1\r\n$4\r\nPING\r\n   -> args:[$4,PING]
-        // get key: *2\r\n$3\r\nGET\r\n$3\r\nkey\r\n -> args:[$3,GET,$3,key]
-        bytes.put(&format!("*{}\r\n", self.args.len() / 2).into_bytes()[..]);
-        bytes.put(&self.args.join("\r\n").into_bytes()[..]);
-        bytes.put(&b"\r\n"[..]);
+        // ping: *1\r\n$4\r\nPING\r\n
+        // get key: *2\r\n$3\r\nGET\r\n$3\r\nkey\r\n
+        let mut bytes = BytesMut::with_capacity(self.args.len() + 16);
+        bytes.put_u8(b'*');
+        write_decimal(&mut bytes, self.len);
+        bytes.put_slice(&self.args);
         bytes
     }
 }
@@ -45,9 +157,9 @@ pub enum ExistOP {
 impl std::str::FromStr for ExistOP {
     type Err = String;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.to_ascii_lowercase() == String::from("nx") {
+        if s.eq_ignore_ascii_case("nx") {
             Ok(ExistOP::NX)
-        } else if s.to_ascii_lowercase() == String::from("xx") {
+        } else if s.eq_ignore_ascii_case("xx") {
             Ok(ExistOP::XX)
         } else {
             Err("unexpected string, 'NX' or 'XX' expected".to_string())
@@ -55,12 +167,109 @@ impl std::str::FromStr for ExistOP {
     }
 }
 
+#[derive(Debug, Clone)]
+pub enum CompareOP {
+    GT,
+    LT,
+}
+
+impl std::str::FromStr for CompareOP {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("gt") {
+            Ok(CompareOP::GT)
+        } else if s.eq_ignore_ascii_case("lt") {
+            Ok(CompareOP::LT)
+        } else {
+            Err("unexpected string, 'GT' or 'LT' expected".to_string())
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum RangeBy {
+    Score,
+    Lex,
+}
+
+impl std::str::FromStr for RangeBy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("byscore") {
+            Ok(RangeBy::Score)
+        } else if s.eq_ignore_ascii_case("bylex") {
+            Ok(RangeBy::Lex)
+        } else {
+            Err("unexpected string, 'BYSCORE' or 'BYLEX' expected".to_string())
+        }
+    }
+}
+
+
+/// Expiration given to a key by SET.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expiry {
+    /// EX seconds
+    Ex(u64),
+    /// PX milliseconds
+    Px(u64),
+    /// EXAT unix time in seconds
+    ExAt(u64),
+    /// PXAT unix time in milliseconds
+    PxAt(u64),
+    /// KEEPTTL: retain the time to live of the old value
+    KeepTtl,
+}
+
+impl Expiry {
+    // expiry_args SET 的过期选项，同属 expiry 组，最多只能给出一个
+    fn expiry_args() -> [Arg<'static, 'static>; 5] {
+        let time = |name, help| Arg::with_name(name).long(name).takes_value(true).validator(u64_arg).help(help);
+        [
+            time("ex", "set key expiration in seconds").short("e"),
+            time("px", "set key expiration in milliseconds").short("p"),
+            time("exat", "set key expiration at a unix time in seconds"),
+            time("pxat", "set key expiration at a unix time in milliseconds"),
+            Arg::with_name("keepttl").long("keepttl").help("keep the time to live of the old value"),
+        ]
+    }
+
+    // from_matches 由 expiry_args 的解析结果得到过期方式，数值已经过 u64_arg 校验
+    fn from_matches(matches: &ArgMatches<'_>) -> Option<Expiry> {
+        let time = |name| matches.value_of(name).and_then(|time| time.parse().ok());
+        if let Some(ex) = time("ex") {
+            Some(Expiry::Ex(ex))
+        } else if let Some(px) = time("px") {
+            Some(Expiry::Px(px))
+        } else if let Some(exat) = time("exat") {
+            Some(Expiry::ExAt(exat))
+        } else if let Some(pxat) = time("pxat") {
+            Some(Expiry::PxAt(pxat))
+        } else if matches.is_present("keepttl") {
+            Some(Expiry::KeepTtl)
+        } else {
+            None
+        }
+    }
+}
+
+// u64_arg 校验过期时间是非负整数
+fn u64_arg(time: String) -> Result<(), String> {
+    time.parse::<u64>().map(|_| ()).map_err(|e| e.to_string())
+}
+
 
 #[derive(Debug, Clone, StructOpt)]
 pub enum Commands {
     /// test server status
     Ping,
 
+    /// handshake with server and switch protocol version
+    Hello {
+        /// protocol version [2|3]
+        protover: Option<u8>,
+    },
+
     /// get string value
     Get {
         /// redis key
@@ -68,6 +277,7 @@ pub enum Commands {
     },
 
     /// set a key with string value
+    #[structopt(group = ArgGroup::with_name("expiry").args(&["ex", "px", "exat", "pxat", "keepttl"]), args = &Expiry::expiry_args())]
     Set {
         /// redis key
         key: String,
@@ -75,16 +285,17 @@ pub enum Commands {
         /// redis key value
         value: String,
 
-        /// set key expiration in seconds, exclusive with px
-        #[structopt(short, long)]
-        ex: Option<u32>,    // SET key value EX 10
-
-        /// set key expiration in milliseconds, exclusive with ex
-        #[structopt(short, long)]
-        px: Option<u32>,    // SET key value PX 10
+        // 一个字段对应 --ex、--px、--exat、--pxat、--keepttl 五个选项，structopt 无法直接生成，
+        // 这些选项由上面的 args 加入，这里从 structopt 生成的 from_clap 中的 matches 取值
+        #[structopt(skip = Expiry::from_matches(matches))]
+        expiry: Option<Expiry>,  // SET key value EX 10 / SET key value KEEPTTL
 
         /// existent flag [NX|XX]
         x: Option<ExistOP>,  // SET key value NX / SET key value XX
+
+        /// return the old value stored at key
+        #[structopt(short, long)]
+        get: bool,          // SET key value GET
     },
 
     /// increase 1
@@ -111,46 +322,847 @@ pub enum Commands {
         /// value
         values: Vec<String>,
     },
+
+    /// set hash fields
+    Hset {
+        /// redis key
+        key: String,
+
+        /// field value [field value ...]
+        #[structopt(required = true, min_values = 2)]
+        field_values: Vec<String>,
+    },
+
+    /// get the value of a hash field
+    Hget {
+        /// redis key
+        key: String,
+
+        /// hash field
+        field: String,
+    },
+
+    /// get all fields and values of a hash
+    Hgetall {
+        /// redis key
+        key: String,
+    },
+
+    /// delete hash fields
+    Hdel {
+        /// redis key
+        key: String,
+
+        /// hash fields
+        #[structopt(required = true)]
+        fields: Vec<String>,
+    },
+
+    /// increase a hash field by an integer
+    Hincrby {
+        /// redis key
+        key: String,
+
+        /// hash field
+        field: String,
+
+        /// increment, may be negative
+        #[structopt(allow_hyphen_values = true)]
+        increment: i64,
+    },
+
+    /// add members to a set
+    Sadd {
+        /// redis key
+        key: String,
+
+        /// members
+        #[structopt(required = true)]
+        members: Vec<String>,
+    },
+
+    /// get all members of a set
+    Smembers {
+        /// redis key
+        key: String,
+    },
+
+    /// check whether a member is in a set
+    Sismember {
+        /// redis key
+        key: String,
+
+        /// member
+        member: String,
+    },
+
+    /// remove members from a set
+    Srem {
+        /// redis key
+        key: String,
+
+        /// members
+        #[structopt(required = true)]
+        members: Vec<String>,
+    },
+
+    /// add members to a sorted set, or update their scores
+    #[structopt(setting = structopt::clap::AppSettings::AllowLeadingHyphen)]
+    Zadd {
+        /// redis key
+        key: String,
+
+        /// existent flag [NX|XX]
+        #[structopt(short, long)]
+        x: Option<ExistOP>,
+
+        /// only update scores that are greater or less than the current one [GT|LT]
+        #[structopt(short, long)]
+        cmp: Option<CompareOP>,
+
+        /// return the number of changed members instead of added members
+        #[structopt(long)]
+        ch: bool,
+
+        /// increase the score of a single member like ZINCRBY
+        #[structopt(long)]
+        incr: bool,
+
+        /// score member [score member ...]
+        #[structopt(required = true, min_values = 2)]
+        score_members: Vec<String>,
+    },
+
+    /// get a range of members of a sorted set
+    Zrange {
+        /// redis key
+        key: String,
+
+        /// start index, or score / lex bound with --by
+        #[structopt(allow_hyphen_values = true)]
+        start: String,
+
+        /// stop index, or score / lex bound with --by
+        #[structopt(allow_hyphen_values = true)]
+        stop: String,
+
+        /// range by score or lex instead of index [BYSCORE|BYLEX]
+        #[structopt(short, long)]
+        by: Option<RangeBy>,
+
+        /// reverse the order
+        #[structopt(long)]
+        rev: bool,
+
+        /// offset and count, only with --by
+        #[structopt(long, number_of_values = 2, value_names = &["offset", "count"], requires = "by", allow_hyphen_values = true)]
+        limit: Option<Vec<i64>>,
+
+        /// return the scores with the members
+        #[structopt(long)]
+        withscores: bool,
+    },
+
+    /// get the score of a sorted set member
+    Zscore {
+        /// redis key
+        key: String,
+
+        /// member
+        member: String,
+    },
+
+    /// delete keys
+    Del {
+        /// redis keys
+        #[structopt(required = true)]
+        keys: Vec<String>,
+    },
+
+    /// count how many of the keys exist
+    Exists {
+        /// redis keys
+        #[structopt(required = true)]
+        keys: Vec<String>,
+    },
+
+    /// set a key's time to live in seconds
+    Expire {
+        /// redis key
+        key: String,
+
+        /// time to live in seconds
+        seconds: u64,
+    },
+
+    /// get a key's time to live in seconds
+    Ttl {
+        /// redis key
+        key: String,
+    },
+
+    /// get the type of the value stored at key
+    Type {
+        /// redis key
+        key: String,
+    },
+
+    /// rename a key
+    Rename {
+        /// redis key
+        key: String,
+
+        /// new name
+        newkey: String,
+    },
+
+    /// iterate over the keyspace, printing keys until the cursor returns to 0
+    Scan {
+        /// cursor to start from
+        #[structopt(default_value = "0")]
+        cursor: u64,
+
+        /// only return keys matching the glob-style pattern
+        #[structopt(short = "m", long = "match")]
+        pattern: Option<String>,
+
+        /// amount of work to do on each call
+        #[structopt(short, long)]
+        count: Option<u64>,
+
+        /// only return keys of the given type, e.g. string, hash, zset
+        #[structopt(short, long = "type")]
+        type_: Option<String>,
+    },
+
+    /// listen for messages published to channels until interrupted
+    Subscribe {
+        /// channels
+        #[structopt(required = true)]
+        channels: Vec<String>,
+    },
+
+    /// listen for messages published to channels matching patterns until interrupted
+    Psubscribe {
+        /// glob-style patterns
+        #[structopt(required = true)]
+        patterns: Vec<String>,
+    },
+
+    /// stop listening to channels, all of them when none is given
+    Unsubscribe {
+        /// channels
+        channels: Vec<String>,
+    },
+
+    /// post a message to a channel
+    Publish {
+        /// channel
+        channel: String,
+
+        /// message
+        message: String,
+    },
+
+    /// append an entry to a stream
+    Xadd {
+        /// redis key
+        key: String,
+
+        /// entry id, generated by the server by default
+        #[structopt(long, default_value = "*")]
+        id: String,
+
+        /// do not create the stream if it does not exist
+        #[structopt(long)]
+        nomkstream: bool,
+
+        /// trim the stream to at most this many entries
+        #[structopt(long)]
+        maxlen: Option<u64>,
+
+        /// trim with `~`, letting the server keep a few more entries for efficiency
+        #[structopt(long, requires = "maxlen")]
+        approx: bool,
+
+        /// field value [field value ...]
+        #[structopt(required = true, min_values = 2)]
+        field_values: Vec<String>,
+    },
+
+    /// read entries from streams, optionally blocking until some arrive
+    Xread {
+        /// maximum number of entries per stream
+        #[structopt(short, long)]
+        count: Option<u64>,
+
+        /// block for this many milliseconds, 0 blocks forever
+        #[structopt(short, long)]
+        block: Option<u64>,
+
+        /// key [key ...] id [id ...], `$` reads only new entries
+        #[structopt(required = true, min_values = 2)]
+        keys_ids: Vec<String>,
+    },
+
+    /// get a range of stream entries
+    Xrange {
+        /// redis key
+        key: String,
+
+        /// start id, `-` for the first entry
+        #[structopt(allow_hyphen_values = true)]
+        start: String,
+
+        /// end id, `+` for the last entry
+        end: String,
+
+        /// maximum number of entries
+        #[structopt(short, long)]
+        count: Option<u64>,
+    },
+
+    /// get a range of stream entries in reverse order
+    Xrevrange {
+        /// redis key
+        key: String,
+
+        /// end id, `+` for the last entry
+        end: String,
+
+        /// start id, `-` for the first entry
+        #[structopt(allow_hyphen_values = true)]
+        start: String,
+
+        /// maximum number of entries
+        #[structopt(short, long)]
+        count: Option<u64>,
+    },
+
+    /// manage consumer groups
+    Xgroup {
+        #[structopt(subcommand)]
+        command: Xgroup,
+    },
+
+    /// read entries from streams as a consumer of a group
+    Xreadgroup {
+        /// consumer group
+        group: String,
+
+        /// consumer name
+        consumer: String,
+
+        /// maximum number of entries per stream
+        #[structopt(short, long)]
+        count: Option<u64>,
+
+        /// block for this many milliseconds, 0 blocks forever
+        #[structopt(short, long)]
+        block: Option<u64>,
+
+        /// do not add the entries to the pending entries list
+        #[structopt(long)]
+        noack: bool,
+
+        /// key [key ...] id [id ...], `>` reads entries never delivered to other consumers
+        #[structopt(required = true, min_values = 2)]
+        keys_ids: Vec<String>,
+    },
+
+    /// acknowledge processed entries of a consumer group
+    Xack {
+        /// redis key
+        key: String,
+
+        /// consumer group
+        group: String,
+
+        /// entry ids
+        #[structopt(required = true)]
+        ids: Vec<String>,
+    },
+
+    /// send any command as is, e.g. `raw HGETALL user:1`
+    #[structopt(setting = structopt::clap::AppSettings::AllowLeadingHyphen)]
+    Raw {
+        /// command name and arguments
+        #[structopt(required = true)]
+        args: Vec<String>,
+    },
+}
+
+/// XGROUP subcommands.
+#[derive(Debug, Clone, StructOpt)]
+pub enum Xgroup {
+    /// create a consumer group
+    Create {
+        /// redis key
+        key: String,
+
+        /// consumer group
+        group: String,
+
+        /// last delivered id, `$` for new entries only, `0` for the whole stream
+        id: String,
+
+        /// create an empty stream if it does not exist
+        #[structopt(long)]
+        mkstream: bool,
+    },
 }
 
 impl Commands {
+    /// Check the argument combinations that structopt can not express.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Commands::Set { expiry: Some(Expiry::Ex(0) | Expiry::Px(0) | Expiry::ExAt(0) | Expiry::PxAt(0)), .. } => {
+                Err("invalid expire time, it must be greater than 0".to_string())
+            }
+            Commands::Hset { field_values, .. } if field_values.len() % 2 != 0 => {
+                Err("HSET expects field value pairs".to_string())
+            }
+            Commands::Zadd { x, cmp, incr, score_members, .. } => {
+                if score_members.len() % 2 != 0 {
+                    return Err("ZADD expects score member pairs".to_string());
+                }
+                if let Some(score) = score_members.iter().step_by(2).find(|score| !is_score(score)) {
+                    return Err(format!("'{}' is not a valid score", score));
+                }
+                if matches!(x, Some(ExistOP::NX)) && cmp.is_some() {
+                    return Err("NX can not be combined with GT or LT".to_string());
+                }
+                if *incr && score_members.len() != 2 {
+                    return Err("INCR expects a single score member pair".to_string());
+                }
+                Ok(())
+            }
+            Commands::Zrange { by: Some(RangeBy::Lex), withscores: true, .. } => {
+                Err("WITHSCORES can not be combined with BYLEX".to_string())
+            }
+            Commands::Xadd { field_values, .. } if field_values.len() % 2 != 0 => {
+                Err("XADD expects field value pairs".to_string())
+            }
+            Commands::Xread { keys_ids, .. } | Commands::Xreadgroup { keys_ids, .. } if keys_ids.len() % 2 != 0 => {
+                Err("expected the same number of keys and ids".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn to_bytes(&self) -> bytes::BytesMut {
         let cmd = match self {
             Commands::Ping => CmdBuilder::new().arg("PING").to_bytes(),
+            Commands::Hello { protover } => {
+                let mut builder = CmdBuilder::new().arg("HELLO");
+                if let Some(protover) = protover {
+                    builder.add_arg(*protover);
+                }
+                builder.to_bytes()
+            }
             Commands::Get { key } => CmdBuilder::new().arg("GET").arg(key).to_bytes(),
             Commands::Set {
-                key, value, ex, px, x
+                key, value, expiry, x, get
             } => {
                 let mut builder = CmdBuilder::new().arg("SET").arg(key).arg(value);
 
-                if let Some(ex) = ex {
-                    builder.add_arg("EX");
-                    builder.add_arg(&ex.to_string());
-                }
-
-                if let Some(px) = px {
-                    builder.add_arg("PX");
-                    builder.add_arg(&px.to_string());
-                }
-
                 if let Some(x) = x {
                     match x {
                         ExistOP::NX => builder.add_arg("NX"),
                         ExistOP::XX => builder.add_arg("XX"),
                     }
                 }
+
+                if *get {
+                    builder.add_arg("GET");
+                }
+
+                match expiry {
+                    Some(Expiry::Ex(ex)) => builder = builder.arg("EX").arg(ex),
+                    Some(Expiry::Px(px)) => builder = builder.arg("PX").arg(px),
+                    Some(Expiry::ExAt(exat)) => builder = builder.arg("EXAT").arg(exat),
+                    Some(Expiry::PxAt(pxat)) => builder = builder.arg("PXAT").arg(pxat),
+                    Some(Expiry::KeepTtl) => builder.add_arg("KEEPTTL"),
+                    None => {}
+                }
                 builder.to_bytes()
             }
             Commands::Incr { key } => CmdBuilder::new().arg("INCR").arg(key).to_bytes(),
             Commands::Lrange { key, start, stop } => CmdBuilder::new()
-                .arg("LRANGE").arg(key).arg(&start.to_string()).arg(&stop.to_string()).to_bytes(),
+                .arg("LRANGE").arg(key).arg(start).arg(stop).to_bytes(),
             Commands::Rpush { key, values } => {
                 let mut builder = CmdBuilder::new().arg("RPUSH").arg(key);
                 values.iter().for_each(|v| builder.add_arg(v));
                 builder.to_bytes()
             }
+            Commands::Hset { key, field_values } => {
+                let mut builder = CmdBuilder::new().arg("HSET").arg(key);
+                field_values.iter().for_each(|v| builder.add_arg(v));
+                builder.to_bytes()
+            }
+            Commands::Hget { key, field } => CmdBuilder::new().arg("HGET").arg(key).arg(field).to_bytes(),
+            Commands::Hgetall { key } => CmdBuilder::new().arg("HGETALL").arg(key).to_bytes(),
+            Commands::Hdel { key, fields } => {
+                let mut builder = CmdBuilder::new().arg("HDEL").arg(key);
+                fields.iter().for_each(|v| builder.add_arg(v));
+                builder.to_bytes()
+            }
+            Commands::Hincrby { key, field, increment } => CmdBuilder::new()
+                .arg("HINCRBY").arg(key).arg(field).arg(increment).to_bytes(),
+            Commands::Sadd { key, members } => {
+                let mut builder = CmdBuilder::new().arg("SADD").arg(key);
+                members.iter().for_each(|v| builder.add_arg(v));
+                builder.to_bytes()
+            }
+            Commands::Smembers { key } => CmdBuilder::new().arg("SMEMBERS").arg(key).to_bytes(),
+            Commands::Sismember { key, member } => CmdBuilder::new().arg("SISMEMBER").arg(key).arg(member).to_bytes(),
+            Commands::Srem { key, members } => {
+                let mut builder = CmdBuilder::new().arg("SREM").arg(key);
+                members.iter().for_each(|v| builder.add_arg(v));
+                builder.to_bytes()
+            }
+            Commands::Zadd { key, x, cmp, ch, incr, score_members } => {
+                let mut builder = CmdBuilder::new().arg("ZADD").arg(key);
+                if let Some(x) = x {
+                    match x {
+                        ExistOP::NX => builder.add_arg("NX"),
+                        ExistOP::XX => builder.add_arg("XX"),
+                    }
+                }
+                if let Some(cmp) = cmp {
+                    match cmp {
+                        CompareOP::GT => builder.add_arg("GT"),
+                        CompareOP::LT => builder.add_arg("LT"),
+                    }
+                }
+                if *ch {
+                    builder.add_arg("CH");
+                }
+                if *incr {
+                    builder.add_arg("INCR");
+                }
+                score_members.iter().for_each(|v| builder.add_arg(v));
+                builder.to_bytes()
+            }
+            Commands::Zrange { key, start, stop, by, rev, limit, withscores } => {
+                let mut builder = CmdBuilder::new().arg("ZRANGE").arg(key).arg(start).arg(stop);
+                if let Some(by) = by {
+                    match by {
+                        RangeBy::Score => builder.add_arg("BYSCORE"),
+                        RangeBy::Lex => builder.add_arg("BYLEX"),
+                    }
+                }
+                if *rev {
+                    builder.add_arg("REV");
+                }
+                if let Some(limit) = limit {
+                    builder.add_arg("LIMIT");
+                    limit.iter().for_each(|n| builder.add_arg(n));
+                }
+                if *withscores {
+                    builder.add_arg("WITHSCORES");
+                }
+                builder.to_bytes()
+            }
+            Commands::Zscore { key, member } => CmdBuilder::new().arg("ZSCORE").arg(key).arg(member).to_bytes(),
+            Commands::Del { keys } => {
+                let mut builder = CmdBuilder::new().arg("DEL");
+                keys.iter().for_each(|k| builder.add_arg(k));
+                builder.to_bytes()
+            }
+            Commands::Exists { keys } => {
+                let mut builder = CmdBuilder::new().arg("EXISTS");
+                keys.iter().for_each(|k| builder.add_arg(k));
+                builder.to_bytes()
+            }
+            Commands::Expire { key, seconds } => CmdBuilder::new()
+                .arg("EXPIRE").arg(key).arg(seconds).to_bytes(),
+            Commands::Ttl { key } => CmdBuilder::new().arg("TTL").arg(key).to_bytes(),
+            Commands::Type { key } => CmdBuilder::new().arg("TYPE").arg(key).to_bytes(),
+            Commands::Rename { key, newkey } => CmdBuilder::new().arg("RENAME").arg(key).arg(newkey).to_bytes(),
+            Commands::Scan { cursor, pattern, count, type_ } => {
+                let mut builder = CmdBuilder::new().arg("SCAN").arg(cursor);
+                if let Some(pattern) = pattern {
+                    builder.add_arg("MATCH");
+                    builder.add_arg(pattern);
+                }
+                if let Some(count) = count {
+                    builder.add_arg("COUNT");
+                    builder.add_arg(count);
+                }
+                if let Some(type_) = type_ {
+                    builder.add_arg("TYPE");
+                    builder.add_arg(type_);
+                }
+                builder.to_bytes()
+            }
+            Commands::Subscribe { channels } => {
+                let mut builder = CmdBuilder::new().arg("SUBSCRIBE");
+                channels.iter().for_each(|c| builder.add_arg(c));
+                builder.to_bytes()
+            }
+            Commands::Psubscribe { patterns } => {
+                let mut builder = CmdBuilder::new().arg("PSUBSCRIBE");
+                patterns.iter().for_each(|p| builder.add_arg(p));
+                builder.to_bytes()
+            }
+            Commands::Unsubscribe { channels } => {
+                let mut builder = CmdBuilder::new().arg("UNSUBSCRIBE");
+                channels.iter().for_each(|c| builder.add_arg(c));
+                builder.to_bytes()
+            }
+            Commands::Publish { channel, message } => CmdBuilder::new()
+                .arg("PUBLISH").arg(channel).arg(message).to_bytes(),
+            Commands::Xadd { key, id, nomkstream, maxlen, approx, field_values } => {
+                let mut builder = CmdBuilder::new().arg("XADD").arg(key);
+                if *nomkstream {
+                    builder.add_arg("NOMKSTREAM");
+                }
+                if let Some(maxlen) = maxlen {
+                    builder.add_arg("MAXLEN");
+                    if *approx {
+                        builder.add_arg("~");
+                    }
+                    builder.add_arg(maxlen);
+                }
+                builder.add_arg(id);
+                field_values.iter().for_each(|v| builder.add_arg(v));
+                builder.to_bytes()
+            }
+            Commands::Xread { count, block, keys_ids } => {
+                let mut builder = CmdBuilder::new().arg("XREAD");
+                read_options(&mut builder, count, block, keys_ids);
+                builder.to_bytes()
+            }
+            Commands::Xrange { key, start, end, count } => {
+                let mut builder = CmdBuilder::new().arg("XRANGE").arg(key).arg(start).arg(end);
+                if let Some(count) = count {
+                    builder.add_arg("COUNT");
+                    builder.add_arg(count);
+                }
+                builder.to_bytes()
+            }
+            Commands::Xrevrange { key, end, start, count } => {
+                let mut builder = CmdBuilder::new().arg("XREVRANGE").arg(key).arg(end).arg(start);
+                if let Some(count) = count {
+                    builder.add_arg("COUNT");
+                    builder.add_arg(count);
+                }
+                builder.to_bytes()
+            }
+            Commands::Xgroup { command: Xgroup::Create { key, group, id, mkstream } } => {
+                let mut builder = CmdBuilder::new().arg("XGROUP").arg("CREATE").arg(key).arg(group).arg(id);
+                if *mkstream {
+                    builder.add_arg("MKSTREAM");
+                }
+                builder.to_bytes()
+            }
+            Commands::Xreadgroup { group, consumer, count, block, noack, keys_ids } => {
+                let mut builder = CmdBuilder::new().arg("XREADGROUP").arg("GROUP").arg(group).arg(consumer);
+                if *noack {
+                    builder.add_arg("NOACK");
+                }
+                read_options(&mut builder, count, block, keys_ids);
+                builder.to_bytes()
+            }
+            Commands::Xack { key, group, ids } => {
+                let mut builder = CmdBuilder::new().arg("XACK").arg(key).arg(group);
+                ids.iter().for_each(|id| builder.add_arg(id));
+                builder.to_bytes()
+            }
+            Commands::Raw { args } => {
+                let mut builder = CmdBuilder::new();
+                args.iter().for_each(|arg| builder.add_arg(arg));
+                builder.to_bytes()
+            }
         };
         debug!("{:?}",cmd);
         cmd
     }
 }
+
+// read_options 编码 XREAD / XREADGROUP 共有的 `[COUNT count] [BLOCK ms] STREAMS key [key ...] id [id ...]`
+fn read_options(builder: &mut CmdBuilder, count: &Option<u64>, block: &Option<u64>, keys_ids: &[String]) {
+    if let Some(count) = count {
+        builder.add_arg("COUNT");
+        builder.add_arg(count);
+    }
+    if let Some(block) = block {
+        builder.add_arg("BLOCK");
+        builder.add_arg(block);
+    }
+    builder.add_arg("STREAMS");
+    keys_ids.iter().for_each(|v| builder.add_arg(v));
+}
+
+// is_score 判断是否为合法的分值：浮点数或 +inf / -inf，不允许 nan
+fn is_score(score: &str) -> bool {
+    score.parse::<f64>().is_ok_and(|score| !score.is_nan())
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::nom::redis::command::*;
+
+    fn parse(args: &[&str]) -> Commands {
+        Commands::from_iter_safe(std::iter::once("parser_toy").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn test_sorted_set_commands() {
+        let zadd = parse(&["zadd", "board", "-x", "xx", "--cmp", "gt", "--ch", "1.5", "a", "-inf", "b"]);
+        assert_eq!(zadd.validate(), Ok(()));
+        assert_eq!(
+            &zadd.to_bytes()[..],
+            b"*9\r\n$4\r\nZADD\r\n$5\r\nboard\r\n$2\r\nXX\r\n$2\r\nGT\r\n$2\r\nCH\r\n$3\r\n1.5\r\n$1\r\na\r\n$4\r\n-inf\r\n$1\r\nb\r\n"
+        );
+
+        let zrange = parse(&["zrange", "board", "(1", "+inf", "--by", "BYSCORE", "--limit", "0", "10", "--withscores"]);
+        assert_eq!(
+            &zrange.to_bytes()[..],
+            &b"*9\r\n$6\r\nZRANGE\r\n$5\r\nboard\r\n$2\r\n(1\r\n$4\r\n+inf\r\n$7\r\nBYSCORE\r\n$5\r\nLIMIT\r\n$1\r\n0\r\n$2\r\n10\r\n$10\r\nWITHSCORES\r\n"[..]
+        );
+        assert!(Commands::from_iter_safe(["parser_toy", "zrange", "board", "0", "-1", "--limit", "0", "10"]).is_err());
+        assert!(Commands::from_iter_safe(["parser_toy", "zadd", "board", "-c", "ge", "1", "a"]).is_err());
+    }
+
+    #[test]
+    fn test_set() {
+        let set = parse(&["set", "k", "v", "--exat", "1700000000", "nx", "--get"]);
+        assert_eq!(set.validate(), Ok(()));
+        assert_eq!(
+            &set.to_bytes()[..],
+            &b"*7\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n$2\r\nNX\r\n$3\r\nGET\r\n$4\r\nEXAT\r\n$10\r\n1700000000\r\n"[..]
+        );
+        assert_eq!(
+            &parse(&["set", "k", "v", "--keepttl"]).to_bytes()[..],
+            b"*4\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n$7\r\nKEEPTTL\r\n"
+        );
+        // 原有的 --ex / --px 写法
+        assert!(matches!(parse(&["set", "k", "v", "--ex", "10"]), Commands::Set { expiry: Some(Expiry::Ex(10)), .. }));
+        assert!(matches!(parse(&["set", "k", "v", "-p", "100"]), Commands::Set { expiry: Some(Expiry::Px(100)), .. }));
+        assert!(matches!(parse(&["set", "k", "v"]), Commands::Set { expiry: None, .. }));
+
+        // 只能给出一个过期选项
+        assert!(Commands::from_iter_safe(["parser_toy", "set", "k", "v", "--ex", "10", "--px", "10"]).is_err());
+        assert!(Commands::from_iter_safe(["parser_toy", "set", "k", "v", "--pxat", "10", "--keepttl"]).is_err());
+        assert!(Commands::from_iter_safe(["parser_toy", "set", "k", "v", "--ex", "ten"]).is_err());
+        assert!(parse(&["set", "k", "v", "--ex", "0"]).validate().is_err());
+
+        let set = Commands::Set {
+            key: "k".to_string(),
+            value: "v".to_string(),
+            expiry: Some(Expiry::Px(1500)),
+            x: None,
+            get: false,
+        };
+        assert_eq!(&set.to_bytes()[..], &b"*5\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n$2\r\nPX\r\n$4\r\n1500\r\n"[..]);
+    }
+
+    #[test]
+    fn test_key_commands() {
+        assert_eq!(&parse(&["del", "a", "b"]).to_bytes()[..], b"*3\r\n$3\r\nDEL\r\n$1\r\na\r\n$1\r\nb\r\n");
+        assert_eq!(&parse(&["exists", "a"]).to_bytes()[..], b"*2\r\n$6\r\nEXISTS\r\n$1\r\na\r\n");
+        assert_eq!(
+            &parse(&["expire", "a", "60"]).to_bytes()[..],
+            b"*3\r\n$6\r\nEXPIRE\r\n$1\r\na\r\n$2\r\n60\r\n"
+        );
+        assert_eq!(&parse(&["ttl", "a"]).to_bytes()[..], b"*2\r\n$3\r\nTTL\r\n$1\r\na\r\n");
+        assert_eq!(&parse(&["type", "a"]).to_bytes()[..], b"*2\r\n$4\r\nTYPE\r\n$1\r\na\r\n");
+        assert_eq!(
+            &parse(&["rename", "a", "b"]).to_bytes()[..],
+            b"*3\r\n$6\r\nRENAME\r\n$1\r\na\r\n$1\r\nb\r\n"
+        );
+        assert!(Commands::from_iter_safe(["parser_toy", "del"]).is_err());
+        assert!(Commands::from_iter_safe(["parser_toy", "expire", "a", "-1"]).is_err());
+    }
+
+    #[test]
+    fn test_scan() {
+        let scan = parse(&["scan", "--match", "user:*", "--count", "100", "--type", "hash"]);
+        assert_eq!(
+            &scan.to_bytes()[..],
+            &b"*8\r\n$4\r\nSCAN\r\n$1\r\n0\r\n$5\r\nMATCH\r\n$6\r\nuser:*\r\n$5\r\nCOUNT\r\n$3\r\n100\r\n$4\r\nTYPE\r\n$4\r\nhash\r\n"[..]
+        );
+        assert_eq!(&parse(&["scan", "17"]).to_bytes()[..], b"*2\r\n$4\r\nSCAN\r\n$2\r\n17\r\n");
+    }
+
+    #[test]
+    fn test_stream_commands() {
+        let xadd = parse(&["xadd", "events", "--maxlen", "1000", "--approx", "type", "login"]);
+        assert_eq!(xadd.validate(), Ok(()));
+        assert_eq!(
+            &xadd.to_bytes()[..],
+            &b"*8\r\n$4\r\nXADD\r\n$6\r\nevents\r\n$6\r\nMAXLEN\r\n$1\r\n~\r\n$4\r\n1000\r\n$1\r\n*\r\n$4\r\ntype\r\n$5\r\nlogin\r\n"[..]
+        );
+
+        let xreadgroup = parse(&["xreadgroup", "workers", "w1", "-c", "10", "-b", "0", "events", ">"]);
+        assert_eq!(xreadgroup.validate(), Ok(()));
+        assert_eq!(
+            &xreadgroup.to_bytes()[..],
+            &b"*11\r\n$10\r\nXREADGROUP\r\n$5\r\nGROUP\r\n$7\r\nworkers\r\n$2\r\nw1\r\n$5\r\nCOUNT\r\n$2\r\n10\r\n$5\r\nBLOCK\r\n$1\r\n0\r\n$7\r\nSTREAMS\r\n$6\r\nevents\r\n$1\r\n>\r\n"[..]
+        );
+
+        assert_eq!(
+            &parse(&["xrange", "events", "-", "+", "-c", "2"]).to_bytes()[..],
+            &b"*6\r\n$6\r\nXRANGE\r\n$6\r\nevents\r\n$1\r\n-\r\n$1\r\n+\r\n$5\r\nCOUNT\r\n$1\r\n2\r\n"[..]
+        );
+        assert_eq!(
+            &parse(&["xgroup", "create", "events", "workers", "$", "--mkstream"]).to_bytes()[..],
+            &b"*6\r\n$6\r\nXGROUP\r\n$6\r\nCREATE\r\n$6\r\nevents\r\n$7\r\nworkers\r\n$1\r\n$\r\n$8\r\nMKSTREAM\r\n"[..]
+        );
+
+        assert!(parse(&["xadd", "events", "type", "login", "at"]).validate().is_err());
+        assert!(parse(&["xread", "a", "b", "0"]).validate().is_err());
+        assert!(Commands::from_iter_safe(["parser_toy", "xadd", "events", "--approx", "type", "login"]).is_err());
+    }
+
+    #[test]
+    fn test_raw() {
+        let raw = parse(&["raw", "HGETALL", "user:1"]);
+        assert_eq!(raw.validate(), Ok(()));
+        assert_eq!(&raw.to_bytes()[..], b"*2\r\n$7\r\nHGETALL\r\n$6\r\nuser:1\r\n");
+        // 以 - 开头的参数原样发送
+        assert_eq!(&parse(&["raw", "INCRBY", "k", "-5"]).to_bytes()[..], b"*3\r\n$6\r\nINCRBY\r\n$1\r\nk\r\n$2\r\n-5\r\n");
+        assert!(Commands::from_iter_safe(["parser_toy", "raw"]).is_err());
+    }
+
+    #[test]
+    fn test_cmd_builder() {
+        let builder = CmdBuilder::new().arg("SET").arg(&b"k\xff"[..]).arg(-12).arg(1.5).arg(f64::INFINITY).arg(1e300);
+        assert_eq!(builder.len(), 6);
+        assert_eq!(
+            &builder.to_bytes()[..],
+            &b"*6\r\n$3\r\nSET\r\n$2\r\nk\xff\r\n$3\r\n-12\r\n$3\r\n1.5\r\n$3\r\ninf\r\n$5\r\n1e300\r\n"[..]
+        );
+        assert_eq!(&CmdBuilder::new().to_bytes()[..], b"*0\r\n");
+        assert_eq!(
+            &CmdBuilder::new().arg(String::from("GET")).arg(Bytes::from_static(b"k")).arg(u64::MAX).to_bytes()[..],
+            &b"*3\r\n$3\r\nGET\r\n$1\r\nk\r\n$20\r\n18446744073709551615\r\n"[..]
+        );
+    }
+
+    #[test]
+    fn test_from_line() {
+        let builder = CmdBuilder::from_line(r#"SET "my key" '{"a":1}' "\x00\xff""#).unwrap();
+        assert_eq!(
+            &builder.to_bytes()[..],
+            &b"*4\r\n$3\r\nSET\r\n$6\r\nmy key\r\n$7\r\n{\"a\":1}\r\n$2\r\n\x00\xff\r\n"[..]
+        );
+        assert!(CmdBuilder::from_line("  ").unwrap().is_empty());
+        assert!(CmdBuilder::from_line(r#"GET "k"#).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(parse(&["hset", "user:1", "name", "a", "age"]).validate().is_err());
+        assert!(parse(&["hset", "user:1", "name", "a", "age", "3"]).validate().is_ok());
+        assert!(parse(&["zadd", "board", "1", "a", "2"]).validate().is_err());
+        assert!(parse(&["zadd", "board", "one", "a"]).validate().is_err());
+        assert!(parse(&["zadd", "board", "nan", "a"]).validate().is_err());
+        assert!(parse(&["zadd", "board", "-x", "nx", "-c", "lt", "1", "a"]).validate().is_err());
+        assert!(parse(&["zadd", "board", "--incr", "1", "a", "2", "b"]).validate().is_err());
+        assert!(parse(&["zrange", "board", "[a", "[z", "--by", "bylex", "--withscores"]).validate().is_err());
+    }
+}
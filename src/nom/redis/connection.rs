@@ -0,0 +1,183 @@
+use std::io;
+
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+use crate::nom::redis::command::{CmdBuilder, Commands, ToArg};
+use crate::nom::redis::decoder::RespDecoder;
+use crate::nom::redis::resp::{Resp, RespLimits};
+
+/// Connection to a Redis server: writes commands and decodes their replies
+/// in order.
+#[derive(Debug)]
+pub struct Connection<S = TcpStream> {
+    stream: S,
+    decoder: RespDecoder,
+}
+
+impl Connection<TcpStream> {
+    pub async fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Ok(Connection::new(TcpStream::connect(addr).await?))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    pub fn new(stream: S) -> Self {
+        Connection::with_limits(stream, RespLimits::default())
+    }
+
+    /// Create a connection whose replies are rejected when they exceed
+    /// `limits`, for servers or proxies that are not trusted.
+    pub fn with_limits(stream: S, limits: RespLimits) -> Self {
+        Connection { stream, decoder: RespDecoder::with_limits(limits) }
+    }
+
+    /// Send one command and read its reply.
+    ///
+    /// Error replies are returned as [`Resp::Err`]; `Err` means the
+    /// connection itself failed.
+    pub async fn request(&mut self, command: &Commands) -> io::Result<Resp> {
+        self.write_all(&command.to_bytes()).await?;
+        self.read_reply().await
+    }
+
+    /// Send every command of `pipeline` in one write, then read exactly one
+    /// reply per command, in the order they were added.
+    ///
+    /// A command that fails on the server only yields a [`Resp::Err`] at its
+    /// position; the other replies are still returned.
+    pub async fn pipeline(&mut self, pipeline: &Pipeline) -> io::Result<Vec<Resp>> {
+        self.write_all(&pipeline.buf).await?;
+        let mut replies = Vec::with_capacity(pipeline.len());
+        for _ in 0..pipeline.len() {
+            replies.push(self.read_reply().await?);
+        }
+        Ok(replies)
+    }
+
+    /// Write already encoded commands.
+    pub async fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.stream.write_all(bytes).await
+    }
+
+    /// Read the next reply, failing if the server closes the connection.
+    pub async fn read_reply(&mut self) -> io::Result<Resp> {
+        self.next_reply()
+            .await?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by server"))
+    }
+
+    /// Read the next reply, or `None` when the server closed the connection
+    /// between two replies.
+    pub async fn next_reply(&mut self) -> io::Result<Option<Resp>> {
+        self.decoder.read_frame(&mut self.stream).await
+    }
+}
+
+/// Commands queued to be sent together with [`Connection::pipeline`].
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    buf: BytesMut,
+    len: usize,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    /// Queue a command.
+    pub fn add(&mut self, command: &Commands) -> &mut Self {
+        self.buf.extend_from_slice(&command.to_bytes());
+        self.len += 1;
+        self
+    }
+
+    /// Queue a command built with [`CmdBuilder`].
+    pub fn add_cmd(&mut self, builder: &CmdBuilder) -> &mut Self {
+        self.buf.extend_from_slice(&builder.to_bytes());
+        self.len += 1;
+        self
+    }
+
+    /// Queue a command given as its argv, e.g. `["HSET", "user:1", "name", "a"]`.
+    pub fn add_argv<T: ToArg>(&mut self, argv: &[T]) -> &mut Self {
+        let mut builder = CmdBuilder::new();
+        argv.iter().for_each(|arg| builder.add_arg(arg));
+        self.add_cmd(&builder)
+    }
+
+    /// Queue all commands of `other` after the ones already queued.
+    pub fn extend(&mut self, other: &Pipeline) -> &mut Self {
+        self.buf.extend_from_slice(&other.buf);
+        self.len += other.len;
+        self
+    }
+
+    /// Number of queued commands.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Drop the queued commands so the pipeline can be reused.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.len = 0;
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::nom::redis::connection::*;
+    use crate::nom::redis::error::RedisError;
+
+    #[tokio::test]
+    async fn test_pipeline() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let server = tokio::spawn(async move {
+            let expected = b"*1\r\n$4\r\nPING\r\n*2\r\n$4\r\nINCR\r\n$1\r\nk\r\n*3\r\n$5\r\nLPUSH\r\n$1\r\nk\r\n$1\r\nv\r\n";
+            let mut request = vec![0; expected.len()];
+            server.read_exact(&mut request).await.unwrap();
+            assert_eq!(&request[..], &expected[..]);
+            // 回复分多次写入，与请求的边界无关
+            for chunk in [&b"+PONG\r\n:"[..], b"1\r\n-WRONGTYPE Operation against a key\r", b"\n"] {
+                server.write_all(chunk).await.unwrap();
+            }
+        });
+
+        let mut conn = Connection::new(client);
+        let mut pipeline = Pipeline::new();
+        pipeline.add(&Commands::Ping).add(&Commands::Incr { key: "k".to_string() }).add_argv(&["LPUSH", "k", "v"]);
+        assert_eq!(pipeline.len(), 3);
+
+        let replies = conn.pipeline(&pipeline).await.unwrap();
+        assert_eq!(replies, vec![
+            Resp::StringLine("PONG".to_string()),
+            Resp::Int(1),
+            Resp::Err(RedisError::WrongType("Operation against a key".to_string())),
+        ]);
+        server.await.unwrap();
+
+        let err = conn.read_reply().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn test_with_limits() {
+        let (client, mut server) = tokio::io::duplex(64);
+        server.write_all(b"*2\r\n$3\r\nfoo\r\n$6\r\nfoobar\r\n").await.unwrap();
+
+        let limits = RespLimits { max_bulk_len: 4, ..RespLimits::default() };
+        let mut conn = Connection::with_limits(client, limits);
+        let err = conn.read_reply().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,315 @@
+use std::io;
+
+use bytes::{Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::nom::redis::error::{RespError, RespErrorKind, RespParseError};
+use crate::nom::redis::resp::{self, Resp, RespLimits};
+
+// 每次从 socket 读取前预留的缓冲区大小
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Stateful RESP decoder for replies that arrive over several reads.
+///
+/// Bytes are appended with [`RespDecoder::feed`] (or read directly with
+/// [`RespDecoder::read_frame`]) and complete frames are taken out one at a
+/// time with [`RespDecoder::decode`]. Leftover bytes of the next frame stay
+/// in the buffer until the following call.
+///
+/// Frames that exceed the decoder's [`RespLimits`] are rejected before they
+/// are buffered in full.
+#[derive(Debug, Default)]
+pub struct RespDecoder {
+    buf: BytesMut,
+    limits: RespLimits,
+    // 当前帧已经扫描过的字节数
+    scanned: usize,
+    // 当前帧中尚未读完的数组剩余元素个数，栈顶为最内层数组
+    pending: Vec<usize>,
+}
+
+impl RespDecoder {
+    pub fn new() -> Self {
+        RespDecoder::default()
+    }
+
+    /// Create a decoder that rejects frames exceeding `limits`.
+    pub fn with_limits(limits: RespLimits) -> Self {
+        RespDecoder { limits, ..RespDecoder::default() }
+    }
+
+    /// Append bytes received from the connection.
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Number of buffered bytes that have not been returned as a frame yet.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Take the next complete frame out of the buffer.
+    ///
+    /// Returns `Ok(None)` when more bytes are needed. On a malformed frame
+    /// the buffered bytes are dropped, because the stream can not be
+    /// re-synchronized after that; the error offset is relative to the start
+    /// of the frame.
+    pub fn decode(&mut self) -> Result<Option<Resp>, RespError> {
+        let frame = match self.next_frame()? {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+        match resp::parse_with_limits(&frame, &self.limits) {
+            Ok(([], resp)) => Ok(Some(resp)),
+            Ok((remain, _)) => Err(self.reset(RespError {
+                kind: RespErrorKind::TrailingBytes,
+                offset: frame.len() - remain.len(),
+            })),
+            Err(e) => Err(self.reset(RespParseError::into_error(e, &frame))),
+        }
+    }
+
+    /// Take the bytes of the next complete frame out of the buffer without
+    /// decoding them, e.g. to parse them with [`RespRef::from_frame`].
+    ///
+    /// The frame boundaries and limits are checked, the values inside are not.
+    ///
+    /// [`RespRef::from_frame`]: crate::nom::redis::resp_ref::RespRef::from_frame
+    pub fn next_frame(&mut self) -> Result<Option<Bytes>, RespError> {
+        match self.scan() {
+            Ok(Some(end)) => {
+                self.scanned = 0;
+                Ok(Some(self.buf.split_to(end).freeze()))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(self.reset(e)),
+        }
+    }
+
+    /// Read from `reader` until a complete frame is available.
+    ///
+    /// Returns `Ok(None)` when the connection is closed between two frames.
+    pub async fn read_frame<R: AsyncRead + Unpin>(&mut self, reader: &mut R) -> io::Result<Option<Resp>> {
+        loop {
+            if let Some(resp) = self.decode()? {
+                return Ok(Some(resp));
+            }
+
+            self.buf.reserve(READ_CHUNK);
+            if reader.read_buf(&mut self.buf).await? == 0 {
+                return if self.buf.is_empty() {
+                    Ok(None)
+                } else {
+                    Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed in the middle of a reply"))
+                };
+            }
+        }
+    }
+
+    // scan 从上次停下的位置继续扫描帧头，帧完整时返回帧的长度；
+    // 已经扫描过的元素不会被重复解析，大数组分多次到达时总开销仍是线性的
+    fn scan(&mut self) -> Result<Option<usize>, RespError> {
+        let max = self.limits.max_frame_size;
+        loop {
+            let rest = &self.buf[self.scanned..];
+            let (remain, count) = match resp::frame_header(rest, &self.limits) {
+                Ok(header) => header,
+                // 帧尚未完整，但已缓冲的内容达到上限，不再继续等待
+                Err(nom::Err::Incomplete(_)) if self.buf.len() >= max => {
+                    return Err(RespError { kind: RespErrorKind::FrameTooLarge { max }, offset: max });
+                }
+                Err(nom::Err::Incomplete(_)) => return Ok(None),
+                Err(e) => return Err(RespParseError::into_error(e, &self.buf)),
+            };
+            self.scanned += rest.len() - remain.len();
+            if self.scanned > max {
+                return Err(RespError { kind: RespErrorKind::FrameTooLarge { max }, offset: max });
+            }
+
+            if count > 0 {
+                if self.pending.len() >= self.limits.max_depth {
+                    return Err(RespError {
+                        kind: RespErrorKind::TooDeep,
+                        offset: self.scanned - (rest.len() - remain.len()),
+                    });
+                }
+                self.pending.push(count);
+                continue;
+            }
+
+            // 一个元素读取完毕，逐层向上减少所在数组的剩余元素个数
+            loop {
+                match self.pending.last_mut() {
+                    None => return Ok(Some(self.scanned)),
+                    Some(left) => {
+                        *left -= 1;
+                        if *left > 0 {
+                            break;
+                        }
+                        self.pending.pop();
+                    }
+                }
+            }
+        }
+    }
+
+    fn reset(&mut self, err: RespError) -> RespError {
+        self.buf.clear();
+        self.scanned = 0;
+        self.pending.clear();
+        err
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::nom::redis::decoder::*;
+    use crate::nom::redis::error::RedisError;
+
+    #[test]
+    fn test_decode_partial() {
+        let mut decoder = RespDecoder::new();
+        let src = b"*2\r\n$3\r\nfoo\r\n$6\r\nba\r\nr!\r\n";
+        for (idx, b) in src.iter().enumerate() {
+            decoder.feed(&[*b]);
+            let resp = decoder.decode().unwrap();
+            if idx + 1 < src.len() {
+                assert_eq!(resp, None);
+            } else {
+                assert_eq!(resp, Some(Resp::MultiBatch(Some(vec![
+                    Resp::Batch(Some(Bytes::from("foo"))),
+                    Resp::Batch(Some(Bytes::from("ba\r\nr!"))),
+                ]))));
+            }
+        }
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[test]
+    fn test_decode_many_frames() {
+        let mut decoder = RespDecoder::new();
+        decoder.feed(b"+OK\r\n:42\r\n*0\r\n$-1\r\n-ERR");
+        assert_eq!(decoder.decode().unwrap(), Some(Resp::StringLine("OK".to_string())));
+        assert_eq!(decoder.decode().unwrap(), Some(Resp::Int(42)));
+        assert_eq!(decoder.decode().unwrap(), Some(Resp::MultiBatch(Some(vec![]))));
+        assert_eq!(decoder.decode().unwrap(), Some(Resp::Batch(None)));
+        assert_eq!(decoder.decode().unwrap(), None);
+        decoder.feed(b" boom\r\n");
+        assert_eq!(decoder.decode().unwrap(), Some(Resp::Err(RedisError::from("ERR boom"))));
+        assert_eq!(decoder.decode().unwrap(), None);
+    }
+
+    #[test]
+    fn test_decode_resp3_frames() {
+        let mut decoder = RespDecoder::new();
+        decoder.feed(b"|1\r\n+ttl\r\n:3600\r\n%1\r\n+a\r\n#t\r\n>2\r\n+message\r\n");
+        assert_eq!(decoder.decode().unwrap(), Some(Resp::Attribute {
+            attrs: vec![(Resp::StringLine("ttl".to_string()), Resp::Int(3600))],
+            reply: Box::new(Resp::Map(vec![(Resp::StringLine("a".to_string()), Resp::Boolean(true))])),
+        }));
+        assert_eq!(decoder.decode().unwrap(), None);
+        decoder.feed(b"=8\r\ntxt:hi\r\n\r\n");
+        assert_eq!(decoder.decode().unwrap(), Some(Resp::Push(vec![
+            Resp::StringLine("message".to_string()),
+            Resp::Verbatim { format: "txt".to_string(), text: Bytes::from("hi\r\n") },
+        ])));
+    }
+
+    #[test]
+    fn test_decode_nested() {
+        let mut decoder = RespDecoder::with_limits(RespLimits { max_depth: 2, ..RespLimits::default() });
+        decoder.feed(b"*2\r\n*1\r\n:1\r\n*-1\r\n");
+        assert_eq!(decoder.decode().unwrap(), Some(Resp::MultiBatch(Some(vec![
+            Resp::MultiBatch(Some(vec![Resp::Int(1)])),
+            Resp::MultiBatch(None),
+        ]))));
+
+        decoder.feed(b"*1\r\n*1\r\n*1\r\n");
+        assert_eq!(decoder.decode(), Err(RespError { kind: RespErrorKind::TooDeep, offset: 8 }));
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[test]
+    fn test_decode_bad_reply() {
+        let mut decoder = RespDecoder::new();
+        decoder.feed(b"?what\r\n+OK\r\n");
+        assert_eq!(decoder.decode(), Err(RespError { kind: RespErrorKind::InvalidPrefix(b'?'), offset: 0 }));
+        assert_eq!(decoder.buffered(), 0);
+
+        decoder.feed(b"*2\r\n:1\r\n:x\r\n");
+        assert_eq!(decoder.decode(), Err(RespError { kind: RespErrorKind::InvalidInteger, offset: 9 }));
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[test]
+    fn test_decode_limits() {
+        let limits = RespLimits { max_bulk_len: 8, max_array_len: 4, max_depth: 4, max_frame_size: 48 };
+        let mut decoder = RespDecoder::with_limits(limits);
+
+        decoder.feed(b"$2147483647\r\n");
+        assert_eq!(decoder.decode(), Err(RespError { kind: RespErrorKind::BulkTooLong { len: 2147483647, max: 8 }, offset: 1 }));
+        assert_eq!(decoder.buffered(), 0);
+
+        decoder.feed(b"*1\r\n*9999999999\r\n");
+        assert_eq!(decoder.decode(), Err(RespError { kind: RespErrorKind::TooManyElements { len: 9999999999, max: 4 }, offset: 5 }));
+
+        // 每个元素都在限制之内，但整个帧超过 48 字节
+        decoder.feed(b"*4\r\n");
+        for _ in 0..3 {
+            decoder.feed(b"$8\r\n12345678\r\n");
+            assert_eq!(decoder.decode(), Ok(None));
+        }
+        decoder.feed(b"$8\r\n12345678\r\n");
+        assert_eq!(decoder.decode(), Err(RespError { kind: RespErrorKind::FrameTooLarge { max: 48 }, offset: 48 }));
+        assert_eq!(decoder.buffered(), 0);
+
+        // 没有结束的行在缓冲达到上限时就报错，不会无限等待
+        decoder.feed(b"+0123456789012345678901234567890123456789012345678901234567890123456789");
+        assert_eq!(decoder.decode(), Err(RespError { kind: RespErrorKind::FrameTooLarge { max: 48 }, offset: 48 }));
+
+        decoder.feed(b"*3\r\n$8\r\n12345678\r\n%1\r\n:1\r\n_\r\n*-1\r\n");
+        assert!(matches!(decoder.decode(), Ok(Some(Resp::MultiBatch(Some(_))))));
+    }
+
+    #[test]
+    fn test_next_frame() {
+        let mut decoder = RespDecoder::new();
+        decoder.feed(b"*2\r\n$3\r\nfoo\r\n:1\r\n+O");
+        let frame = decoder.next_frame().unwrap().unwrap();
+        assert_eq!(&frame[..], b"*2\r\n$3\r\nfoo\r\n:1\r\n");
+        assert_eq!(decoder.next_frame().unwrap(), None);
+        decoder.feed(b"K\r\n");
+        assert_eq!(decoder.decode().unwrap(), Some(Resp::StringLine("OK".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_read_large_lrange() {
+        let value = "v".repeat(100);
+        let count = 40_000;
+        let mut src = format!("*{}\r\n", count).into_bytes();
+        for _ in 0..count {
+            src.extend_from_slice(format!("${}\r\n{}\r\n", value.len(), value).as_bytes());
+        }
+        src.extend_from_slice(b"+OK\r\n");
+
+        let (mut client, mut server) = tokio::io::duplex(1460);
+        let writer = tokio::spawn(async move {
+            use tokio::io::AsyncWriteExt;
+            server.write_all(&src).await.unwrap();
+        });
+
+        let mut decoder = RespDecoder::new();
+        match decoder.read_frame(&mut client).await.unwrap() {
+            Some(Resp::MultiBatch(Some(items))) => {
+                assert_eq!(items.len(), count);
+                assert!(items.iter().all(|item| item == &Resp::Batch(Some(Bytes::from(value.clone())))));
+            }
+            other => panic!("unexpected reply: {:?}", other),
+        }
+        assert_eq!(decoder.read_frame(&mut client).await.unwrap(), Some(Resp::StringLine("OK".to_string())));
+
+        writer.await.unwrap();
+        assert_eq!(decoder.read_frame(&mut client).await.unwrap(), None);
+    }
+}
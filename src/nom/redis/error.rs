@@ -0,0 +1,250 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+
+use nom::bytes::complete::take_while1;
+use nom::character::complete::{char, digit1};
+use nom::combinator::{all_consuming, map_res};
+use nom::error::{ErrorKind, ParseError};
+use nom::IResult;
+use nom::sequence::separated_pair;
+
+/// Why a RESP frame could not be decoded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RespErrorKind {
+    /// The input ends before the frame is complete.
+    Incomplete,
+    /// The type byte does not start any known RESP type.
+    InvalidPrefix(u8),
+    /// A bulk or aggregate length is not a valid length.
+    InvalidLength,
+    /// An integer reply is not a number.
+    InvalidInteger,
+    /// An integer reply does not fit in an `i64`.
+    IntegerOverflow,
+    /// A double, boolean, big number or verbatim string is malformed.
+    InvalidValue,
+    /// A simple string or error line is not valid UTF-8.
+    Utf8,
+    /// A line or bulk payload is not terminated by `\r\n`.
+    MissingCrlf,
+    /// An aggregate holds fewer elements than its declared count.
+    CountMismatch { expected: usize, got: usize },
+    /// Aggregates are nested deeper than the configured maximum.
+    TooDeep,
+    /// A bulk string is longer than `RespLimits::max_bulk_len`.
+    BulkTooLong { len: u64, max: usize },
+    /// An aggregate declares more items than `RespLimits::max_array_len`.
+    TooManyElements { len: u64, max: usize },
+    /// A frame is larger than `RespLimits::max_frame_size`.
+    FrameTooLarge { max: usize },
+    /// Bytes are left over after a complete frame.
+    TrailingBytes,
+    /// An inline request has a quoted argument without its closing quote.
+    UnbalancedQuotes,
+}
+
+impl Display for RespErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RespErrorKind::Incomplete => write!(f, "incomplete frame"),
+            RespErrorKind::InvalidPrefix(c) => write!(f, "invalid type prefix '{}'", c.escape_ascii()),
+            RespErrorKind::InvalidLength => write!(f, "invalid length"),
+            RespErrorKind::InvalidInteger => write!(f, "invalid integer"),
+            RespErrorKind::IntegerOverflow => write!(f, "integer overflow"),
+            RespErrorKind::InvalidValue => write!(f, "invalid value"),
+            RespErrorKind::Utf8 => write!(f, "invalid utf-8"),
+            RespErrorKind::MissingCrlf => write!(f, "expected \\r\\n"),
+            RespErrorKind::CountMismatch { expected, got } => write!(f, "expected {} items, got {}", expected, got),
+            RespErrorKind::TooDeep => write!(f, "aggregates nested too deep"),
+            RespErrorKind::BulkTooLong { len, max } => write!(f, "bulk length {} exceeds limit {}", len, max),
+            RespErrorKind::TooManyElements { len, max } => write!(f, "aggregate length {} exceeds limit {}", len, max),
+            RespErrorKind::FrameTooLarge { max } => write!(f, "frame exceeds limit of {} bytes", max),
+            RespErrorKind::TrailingBytes => write!(f, "trailing bytes after frame"),
+            RespErrorKind::UnbalancedQuotes => write!(f, "unbalanced quotes in request"),
+        }
+    }
+}
+
+/// Protocol error of the RESP layer, with the byte offset in the frame
+/// where decoding failed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RespError {
+    pub kind: RespErrorKind,
+    pub offset: usize,
+}
+
+impl Display for RespError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.kind, self.offset)
+    }
+}
+
+impl Error for RespError {}
+
+impl From<RespError> for std::io::Error {
+    fn from(e: RespError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Error type of the nom parsers in `resp`: the remaining input where
+/// parsing failed and the reason.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RespParseError<'a> {
+    pub input: &'a [u8],
+    pub kind: RespErrorKind,
+}
+
+impl<'a> RespParseError<'a> {
+    pub fn new(input: &'a [u8], kind: RespErrorKind) -> Self {
+        RespParseError { input, kind }
+    }
+
+    /// Convert a parser error into a [`RespError`] whose offset is relative
+    /// to `src`, the input the parser was started on.
+    pub fn into_error(e: nom::Err<RespParseError<'a>>, src: &[u8]) -> RespError {
+        match e {
+            nom::Err::Incomplete(_) => RespError { kind: RespErrorKind::Incomplete, offset: src.len() },
+            nom::Err::Error(e) | nom::Err::Failure(e) => RespError {
+                kind: e.kind,
+                offset: src.len() - e.input.len(),
+            },
+        }
+    }
+}
+
+impl<'a> ParseError<&'a [u8]> for RespParseError<'a> {
+    // nom 内置解析器失败时的默认分类，其余情况由 resp 中的解析器显式指定
+    fn from_error_kind(input: &'a [u8], kind: ErrorKind) -> Self {
+        let kind = match kind {
+            ErrorKind::Tag => RespErrorKind::MissingCrlf,
+            ErrorKind::Char | ErrorKind::OneOf => RespErrorKind::InvalidPrefix(input.first().copied().unwrap_or_default()),
+            _ => RespErrorKind::InvalidValue,
+        };
+        RespParseError { input, kind }
+    }
+
+    fn append(_: &'a [u8], _: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+/// Error reply sent by the server (`-CODE message`), classified by its code
+/// so callers can decide whether to redirect or retry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedisError {
+    /// `MOVED <slot> <addr>`: the slot is served by another cluster node.
+    Moved { slot: u16, addr: String },
+    /// `ASK <slot> <addr>`: the slot is being migrated, ask the node once.
+    Ask { slot: u16, addr: String },
+    /// `WRONGTYPE`: the key holds a value of another type.
+    WrongType(String),
+    /// `NOSCRIPT`: no script matches the SHA1 given to EVALSHA.
+    NoScript(String),
+    /// `BUSY`: a script or function is running.
+    Busy(String),
+    /// `LOADING`: the dataset is being loaded into memory.
+    Loading(String),
+    /// `READONLY`: writes against a read only replica.
+    ReadOnly(String),
+    /// Any other error, such as `ERR` or `EXECABORT`.
+    Generic { code: String, message: String },
+}
+
+impl RedisError {
+    /// The error code, the first word of the error line.
+    pub fn code(&self) -> &str {
+        match self {
+            RedisError::Moved { .. } => "MOVED",
+            RedisError::Ask { .. } => "ASK",
+            RedisError::WrongType(_) => "WRONGTYPE",
+            RedisError::NoScript(_) => "NOSCRIPT",
+            RedisError::Busy(_) => "BUSY",
+            RedisError::Loading(_) => "LOADING",
+            RedisError::ReadOnly(_) => "READONLY",
+            RedisError::Generic { code, .. } => code,
+        }
+    }
+}
+
+// redirect 解析 MOVED / ASK 的 `<slot> <addr>` 部分
+fn redirect(i: &str) -> IResult<&str, (u16, &str)> {
+    all_consuming(separated_pair(
+        map_res(digit1, str::parse::<u16>),
+        char(' '),
+        take_while1(|c: char| !c.is_whitespace()),
+    ))(i)
+}
+
+impl From<&str> for RedisError {
+    fn from(line: &str) -> Self {
+        let (code, message) = line.split_once(' ').unwrap_or((line, ""));
+        match code {
+            "MOVED" | "ASK" => match redirect(message) {
+                Ok((_, (slot, addr))) if code == "MOVED" => RedisError::Moved { slot, addr: addr.to_string() },
+                Ok((_, (slot, addr))) => RedisError::Ask { slot, addr: addr.to_string() },
+                Err(_) => RedisError::Generic { code: code.to_string(), message: message.to_string() },
+            },
+            "WRONGTYPE" => RedisError::WrongType(message.to_string()),
+            "NOSCRIPT" => RedisError::NoScript(message.to_string()),
+            "BUSY" => RedisError::Busy(message.to_string()),
+            "LOADING" => RedisError::Loading(message.to_string()),
+            "READONLY" => RedisError::ReadOnly(message.to_string()),
+            _ => RedisError::Generic { code: code.to_string(), message: message.to_string() },
+        }
+    }
+}
+
+impl Display for RedisError {
+    // 输出与服务端发送的错误行一致
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            RedisError::Moved { slot, addr } | RedisError::Ask { slot, addr } => {
+                return write!(f, "{} {} {}", self.code(), slot, addr);
+            }
+            RedisError::WrongType(message)
+            | RedisError::NoScript(message)
+            | RedisError::Busy(message)
+            | RedisError::Loading(message)
+            | RedisError::ReadOnly(message)
+            | RedisError::Generic { message, .. } => message,
+        };
+        if message.is_empty() {
+            write!(f, "{}", self.code())
+        } else {
+            write!(f, "{} {}", self.code(), message)
+        }
+    }
+}
+
+impl Error for RedisError {}
+
+
+#[cfg(test)]
+mod test {
+    use crate::nom::redis::error::*;
+
+    #[test]
+    fn test_redis_error() {
+        let cases = [
+            ("MOVED 3999 127.0.0.1:6381", RedisError::Moved { slot: 3999, addr: "127.0.0.1:6381".to_string() }),
+            ("ASK 3999 127.0.0.1:6381", RedisError::Ask { slot: 3999, addr: "127.0.0.1:6381".to_string() }),
+            (
+                "WRONGTYPE Operation against a key holding the wrong kind of value",
+                RedisError::WrongType("Operation against a key holding the wrong kind of value".to_string()),
+            ),
+            ("NOSCRIPT No matching script. Please use EVAL.", RedisError::NoScript("No matching script. Please use EVAL.".to_string())),
+            ("BUSY Redis is busy running a script.", RedisError::Busy("Redis is busy running a script.".to_string())),
+            ("LOADING Redis is loading the dataset in memory", RedisError::Loading("Redis is loading the dataset in memory".to_string())),
+            ("READONLY You can't write against a read only replica.", RedisError::ReadOnly("You can't write against a read only replica.".to_string())),
+            ("ERR unknown command 'FOO'", RedisError::Generic { code: "ERR".to_string(), message: "unknown command 'FOO'".to_string() }),
+            ("Error", RedisError::Generic { code: "Error".to_string(), message: "".to_string() }),
+            ("MOVED abc 127.0.0.1:6381", RedisError::Generic { code: "MOVED".to_string(), message: "abc 127.0.0.1:6381".to_string() }),
+            ("ASK 70000 127.0.0.1:6381", RedisError::Generic { code: "ASK".to_string(), message: "70000 127.0.0.1:6381".to_string() }),
+        ];
+        for (line, err) in cases {
+            assert_eq!(RedisError::from(line), err);
+            assert_eq!(err.to_string(), line);
+        }
+    }
+}
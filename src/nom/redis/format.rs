@@ -0,0 +1,333 @@
+use std::io::Write;
+
+use crate::nom::redis::resp::Resp;
+
+/// How `redis_cli` prints replies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputMode {
+    /// Same output as redis-cli on a terminal: `(integer) 1`, `"value"`, numbered lists.
+    #[default]
+    Default,
+    /// Plain values without type hints or quoting, for scripting.
+    Raw,
+    /// The reply encoded as one JSON value.
+    Json,
+}
+
+/// Format a reply for printing, including the trailing newline.
+///
+/// The result is bytes because raw mode writes values unescaped.
+pub fn format_reply(resp: &Resp, mode: OutputMode) -> Vec<u8> {
+    let mut out = Vec::new();
+    match mode {
+        OutputMode::Default => tty(&mut out, resp, ""),
+        OutputMode::Raw => {
+            raw(&mut out, resp);
+            out.push(b'\n');
+        }
+        OutputMode::Json => {
+            json(&mut out, resp);
+            out.push(b'\n');
+        }
+    }
+    out
+}
+
+// repr 按 redis-cli 的规则输出带引号的字符串，不可打印字符转义为 \xHH
+fn repr(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.push(b'"');
+    for &c in bytes {
+        match c {
+            b'\\' | b'"' => out.extend_from_slice(&[b'\\', c]),
+            b'\n' => out.extend_from_slice(b"\\n"),
+            b'\r' => out.extend_from_slice(b"\\r"),
+            b'\t' => out.extend_from_slice(b"\\t"),
+            0x07 => out.extend_from_slice(b"\\a"),
+            0x08 => out.extend_from_slice(b"\\b"),
+            0x20..=0x7e => out.push(c),
+            _ => {
+                let _ = write!(out, "\\x{:02x}", c);
+            }
+        }
+    }
+    out.push(b'"');
+}
+
+// tty 默认模式，每个回复以换行结尾；prefix 为嵌套列表中后续行的缩进
+fn tty(out: &mut Vec<u8>, resp: &Resp, prefix: &str) {
+    match resp {
+        Resp::StringLine(line) => {
+            let _ = writeln!(out, "{}", line);
+        }
+        Resp::Err(err) => {
+            let _ = writeln!(out, "(error) {}", err);
+        }
+        Resp::Int(int) => {
+            let _ = writeln!(out, "(integer) {}", int);
+        }
+        Resp::Double(d) => {
+            let _ = writeln!(out, "(double) {}", d);
+        }
+        Resp::BigNumber(n) => {
+            let _ = writeln!(out, "(big number) {}", n);
+        }
+        Resp::Boolean(b) => {
+            let _ = writeln!(out, "({})", b);
+        }
+        Resp::Batch(None) | Resp::MultiBatch(None) | Resp::Null => out.extend_from_slice(b"(nil)\n"),
+        Resp::Batch(Some(bytes)) => {
+            repr(out, bytes);
+            out.push(b'\n');
+        }
+        Resp::Verbatim { text, .. } => {
+            out.extend_from_slice(text);
+            out.push(b'\n');
+        }
+        Resp::MultiBatch(Some(items)) | Resp::Push(items) => {
+            tty_list(out, items.iter().map(|item| (item, None)), items.len(), ')', "(empty array)", prefix)
+        }
+        Resp::Set(items) => tty_list(out, items.iter().map(|item| (item, None)), items.len(), '~', "(empty set)", prefix),
+        Resp::Map(pairs) => tty_list(out, pairs.iter().map(|(k, v)| (k, Some(v))), pairs.len(), '#', "(empty hash)", prefix),
+        // redis-cli 不展示属性，只输出真正的回复
+        Resp::Attribute { reply, .. } => tty(out, reply, prefix),
+    }
+}
+
+// tty_list 输出编号列表，如 `1) "a"`；map 的条目输出为 `1# "k" => "v"`
+fn tty_list<'a>(
+    out: &mut Vec<u8>,
+    entries: impl Iterator<Item = (&'a Resp, Option<&'a Resp>)>,
+    len: usize,
+    sep: char,
+    empty: &str,
+    prefix: &str,
+) {
+    if len == 0 {
+        let _ = writeln!(out, "{}", empty);
+        return;
+    }
+
+    // 序号按最大序号的位数右对齐，嵌套列表的缩进随之增加
+    let width = len.to_string().len();
+    let nested = format!("{}{}", prefix, " ".repeat(width + 2));
+    for (idx, (item, value)) in entries.enumerate() {
+        let _ = write!(out, "{}{:>width$}{} ", if idx == 0 { "" } else { prefix }, idx + 1, sep, width = width);
+        tty(out, item, &nested);
+        if let Some(value) = value {
+            out.pop();
+            out.extend_from_slice(b" => ");
+            tty(out, value, &nested);
+        }
+    }
+}
+
+// raw 原样输出内容，数组元素之间以换行分隔
+fn raw(out: &mut Vec<u8>, resp: &Resp) {
+    match resp {
+        Resp::StringLine(line) => out.extend_from_slice(line.as_bytes()),
+        Resp::Err(err) => {
+            let _ = write!(out, "{}", err);
+        }
+        Resp::Int(int) => {
+            let _ = write!(out, "{}", int);
+        }
+        Resp::Double(d) => {
+            let _ = write!(out, "{}", d);
+        }
+        Resp::BigNumber(n) => out.extend_from_slice(n.as_bytes()),
+        Resp::Boolean(b) => {
+            let _ = write!(out, "({})", b);
+        }
+        Resp::Batch(None) | Resp::MultiBatch(None) | Resp::Null => {}
+        Resp::Batch(Some(bytes)) | Resp::Verbatim { text: bytes, .. } => out.extend_from_slice(bytes),
+        Resp::MultiBatch(Some(items)) | Resp::Set(items) | Resp::Push(items) => {
+            for (idx, item) in items.iter().enumerate() {
+                if idx > 0 {
+                    out.push(b'\n');
+                }
+                raw(out, item);
+            }
+        }
+        Resp::Map(pairs) => {
+            for (idx, (k, v)) in pairs.iter().enumerate() {
+                if idx > 0 {
+                    out.push(b'\n');
+                }
+                raw(out, k);
+                out.push(b' ');
+                raw(out, v);
+            }
+        }
+        Resp::Attribute { reply, .. } => raw(out, reply),
+    }
+}
+
+// json_str 输出 JSON 字符串，非法 utf-8 的字节按 \u00XX 输出
+fn json_str(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.push(b'"');
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            let _ = match c {
+                '"' => write!(out, "\\\""),
+                '\\' => write!(out, "\\\\"),
+                '\n' => write!(out, "\\n"),
+                '\r' => write!(out, "\\r"),
+                '\t' => write!(out, "\\t"),
+                c if c.is_control() => write!(out, "\\u{:04x}", c as u32),
+                c => write!(out, "{}", c),
+            };
+        }
+        for b in chunk.invalid() {
+            let _ = write!(out, "\\u{:04x}", b);
+        }
+    }
+    out.push(b'"');
+}
+
+// json_key 将 map 的键转换为 JSON 对象的键
+fn json_key(out: &mut Vec<u8>, key: &Resp) {
+    match key {
+        Resp::StringLine(line) => json_str(out, line.as_bytes()),
+        Resp::Batch(Some(bytes)) | Resp::Verbatim { text: bytes, .. } => json_str(out, bytes),
+        key => {
+            let mut text = Vec::new();
+            raw(&mut text, key);
+            json_str(out, &text);
+        }
+    }
+}
+
+// json 输出一个 JSON 值
+fn json(out: &mut Vec<u8>, resp: &Resp) {
+    match resp {
+        Resp::StringLine(line) => json_str(out, line.as_bytes()),
+        Resp::Err(err) => {
+            out.extend_from_slice(b"{\"error\":");
+            json_str(out, err.to_string().as_bytes());
+            out.push(b'}');
+        }
+        Resp::Int(int) => {
+            let _ = write!(out, "{}", int);
+        }
+        // JSON 没有 inf 与 nan，以字符串输出
+        Resp::Double(d) if !d.is_finite() => json_str(out, d.to_string().as_bytes()),
+        Resp::Double(d) => {
+            let _ = write!(out, "{}", d);
+        }
+        Resp::BigNumber(n) => out.extend_from_slice(n.as_bytes()),
+        Resp::Boolean(b) => {
+            let _ = write!(out, "{}", b);
+        }
+        Resp::Batch(None) | Resp::MultiBatch(None) | Resp::Null => out.extend_from_slice(b"null"),
+        Resp::Batch(Some(bytes)) | Resp::Verbatim { text: bytes, .. } => json_str(out, bytes),
+        Resp::MultiBatch(Some(items)) | Resp::Set(items) | Resp::Push(items) => {
+            out.push(b'[');
+            for (idx, item) in items.iter().enumerate() {
+                if idx > 0 {
+                    out.push(b',');
+                }
+                json(out, item);
+            }
+            out.push(b']');
+        }
+        Resp::Map(pairs) => {
+            out.push(b'{');
+            for (idx, (k, v)) in pairs.iter().enumerate() {
+                if idx > 0 {
+                    out.push(b',');
+                }
+                json_key(out, k);
+                out.push(b':');
+                json(out, v);
+            }
+            out.push(b'}');
+        }
+        Resp::Attribute { reply, .. } => json(out, reply),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::nom::redis::error::RedisError;
+    use crate::nom::redis::format::*;
+
+    fn batch(s: &[u8]) -> Resp {
+        Resp::Batch(Some(Bytes::copy_from_slice(s)))
+    }
+
+    fn tty_string(resp: &Resp) -> String {
+        String::from_utf8(format_reply(resp, OutputMode::Default)).unwrap()
+    }
+
+    #[test]
+    fn test_default_scalar() {
+        assert_eq!(tty_string(&Resp::StringLine("OK".to_string())), "OK\n");
+        assert_eq!(tty_string(&Resp::Int(1)), "(integer) 1\n");
+        assert_eq!(tty_string(&batch(b"value")), "\"value\"\n");
+        assert_eq!(tty_string(&batch(b"a\"b\\\n\x00\xe4")), "\"a\\\"b\\\\\\n\\x00\\xe4\"\n");
+        assert_eq!(tty_string(&Resp::Batch(None)), "(nil)\n");
+        assert_eq!(tty_string(&Resp::Null), "(nil)\n");
+        assert_eq!(tty_string(&Resp::Err(RedisError::from("ERR boom"))), "(error) ERR boom\n");
+        assert_eq!(tty_string(&Resp::Double(1.5)), "(double) 1.5\n");
+        assert_eq!(tty_string(&Resp::Boolean(true)), "(true)\n");
+        assert_eq!(tty_string(&Resp::MultiBatch(Some(vec![]))), "(empty array)\n");
+    }
+
+    #[test]
+    fn test_default_nested() {
+        let items = (1..=10).map(|i| batch(i.to_string().as_bytes())).collect();
+        assert_eq!(
+            tty_string(&Resp::MultiBatch(Some(items))),
+            " 1) \"1\"\n 2) \"2\"\n 3) \"3\"\n 4) \"4\"\n 5) \"5\"\n 6) \"6\"\n 7) \"7\"\n 8) \"8\"\n 9) \"9\"\n10) \"10\"\n"
+        );
+
+        // SCAN
+        let scan = Resp::MultiBatch(Some(vec![
+            batch(b"0"),
+            Resp::MultiBatch(Some(vec![batch(b"key1"), batch(b"key2")])),
+        ]));
+        assert_eq!(tty_string(&scan), "1) \"0\"\n2) 1) \"key1\"\n   2) \"key2\"\n");
+
+        let deep = Resp::MultiBatch(Some(vec![Resp::MultiBatch(Some(vec![
+            Resp::Int(1),
+            Resp::MultiBatch(Some(vec![Resp::Batch(None), Resp::MultiBatch(Some(vec![]))])),
+        ]))]));
+        assert_eq!(
+            tty_string(&deep),
+            "1) 1) (integer) 1\n   2) 1) (nil)\n      2) (empty array)\n"
+        );
+
+        let map = Resp::Map(vec![
+            (batch(b"server"), batch(b"redis")),
+            (batch(b"modules"), Resp::MultiBatch(Some(vec![]))),
+        ]);
+        assert_eq!(tty_string(&map), "1# \"server\" => \"redis\"\n2# \"modules\" => (empty array)\n");
+    }
+
+    #[test]
+    fn test_raw() {
+        let lrange = Resp::MultiBatch(Some(vec![batch(b"a"), batch(b"\xff\x00"), Resp::Batch(None)]));
+        assert_eq!(format_reply(&lrange, OutputMode::Raw), b"a\n\xff\x00\n\n");
+        assert_eq!(format_reply(&Resp::Int(3), OutputMode::Raw), b"3\n");
+        assert_eq!(format_reply(&Resp::Batch(None), OutputMode::Raw), b"\n");
+    }
+
+    #[test]
+    fn test_json() {
+        let reply = Resp::MultiBatch(Some(vec![
+            batch(b"a\"\n"),
+            Resp::Int(-1),
+            Resp::Batch(None),
+            Resp::Map(vec![(batch(b"k"), Resp::Boolean(true)), (Resp::Int(2), Resp::Double(f64::INFINITY))]),
+            batch(b"\xff"),
+            Resp::Err(RedisError::from("ERR boom")),
+        ]));
+        assert_eq!(
+            String::from_utf8(format_reply(&reply, OutputMode::Json)).unwrap(),
+            "[\"a\\\"\\n\",-1,null,{\"k\":true,\"2\":\"inf\"},\"\\u00ff\",{\"error\":\"ERR boom\"}]\n"
+        );
+    }
+}
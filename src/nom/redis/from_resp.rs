@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::hash::Hash;
+
+use bytes::Bytes;
+
+use crate::nom::redis::error::RedisError;
+use crate::nom::redis::resp::Resp;
+
+/// Error returned when a reply can not be converted to the requested type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FromRespError {
+    /// The server answered with an error reply.
+    Server(RedisError),
+    /// The reply has a different type than the one expected.
+    Unexpected { expected: &'static str, got: &'static str },
+}
+
+impl FromRespError {
+    fn unexpected(expected: &'static str, got: &Resp) -> Self {
+        match got {
+            Resp::Err(err) => FromRespError::Server(err.clone()),
+            _ => FromRespError::Unexpected { expected, got: got.kind() },
+        }
+    }
+}
+
+impl Display for FromRespError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FromRespError::Server(err) => write!(f, "server error: {}", err),
+            FromRespError::Unexpected { expected, got } => write!(f, "expected {}, got {}", expected, got),
+        }
+    }
+}
+
+impl Error for FromRespError {}
+
+/// Conversion from a decoded reply into a Rust type.
+pub trait FromResp: Sized {
+    fn from_resp(resp: Resp) -> Result<Self, FromRespError>;
+}
+
+// text 取出回复中的文本内容（二进制安全）
+fn text(resp: &Resp) -> Option<&[u8]> {
+    match resp {
+        Resp::StringLine(line) => Some(line.as_bytes()),
+        Resp::Batch(Some(bytes)) => Some(bytes),
+        Resp::Verbatim { text, .. } => Some(text),
+        Resp::BigNumber(n) => Some(n.as_bytes()),
+        _ => None,
+    }
+}
+
+// number 将文本内容解析为数值，如 GET 返回的计数器或 ZSCORE 返回的分值
+fn number<T: std::str::FromStr>(resp: &Resp) -> Option<T> {
+    std::str::from_utf8(text(resp)?).ok()?.parse().ok()
+}
+
+// items 取出聚合类型中的元素，空数组视为没有元素
+fn items(resp: Resp, expected: &'static str) -> Result<Vec<Resp>, FromRespError> {
+    match resp {
+        Resp::MultiBatch(Some(items)) | Resp::Set(items) | Resp::Push(items) => Ok(items),
+        Resp::MultiBatch(None) | Resp::Null => Ok(vec![]),
+        resp => Err(FromRespError::unexpected(expected, &resp)),
+    }
+}
+
+impl FromResp for Resp {
+    fn from_resp(resp: Resp) -> Result<Self, FromRespError> {
+        Ok(resp)
+    }
+}
+
+impl FromResp for i64 {
+    fn from_resp(resp: Resp) -> Result<Self, FromRespError> {
+        match resp {
+            Resp::Int(int) => Ok(int),
+            resp => number(&resp).ok_or_else(|| FromRespError::unexpected("Int", &resp)),
+        }
+    }
+}
+
+impl FromResp for u64 {
+    fn from_resp(resp: Resp) -> Result<Self, FromRespError> {
+        match resp {
+            Resp::Int(int) if int >= 0 => Ok(int as u64),
+            resp => number(&resp).ok_or_else(|| FromRespError::unexpected("unsigned Int", &resp)),
+        }
+    }
+}
+
+impl FromResp for f64 {
+    fn from_resp(resp: Resp) -> Result<Self, FromRespError> {
+        match resp {
+            Resp::Double(d) => Ok(d),
+            Resp::Int(int) => Ok(int as f64),
+            resp => number(&resp).ok_or_else(|| FromRespError::unexpected("Double", &resp)),
+        }
+    }
+}
+
+impl FromResp for bool {
+    fn from_resp(resp: Resp) -> Result<Self, FromRespError> {
+        match resp {
+            Resp::Boolean(b) => Ok(b),
+            Resp::Int(0) => Ok(false),
+            Resp::Int(1) => Ok(true),
+            resp => Err(FromRespError::unexpected("Boolean", &resp)),
+        }
+    }
+}
+
+impl FromResp for String {
+    fn from_resp(resp: Resp) -> Result<Self, FromRespError> {
+        text(&resp)
+            .and_then(|t| String::from_utf8(t.to_vec()).ok())
+            .ok_or_else(|| FromRespError::unexpected("UTF-8 string", &resp))
+    }
+}
+
+impl FromResp for Bytes {
+    fn from_resp(resp: Resp) -> Result<Self, FromRespError> {
+        match resp {
+            Resp::Batch(Some(bytes)) => Ok(bytes),
+            Resp::Verbatim { text, .. } => Ok(text),
+            resp => text(&resp).map(Bytes::copy_from_slice).ok_or_else(|| FromRespError::unexpected("Batch", &resp)),
+        }
+    }
+}
+
+impl FromResp for Vec<u8> {
+    fn from_resp(resp: Resp) -> Result<Self, FromRespError> {
+        Bytes::from_resp(resp).map(|bytes| bytes.to_vec())
+    }
+}
+
+impl<T: FromResp> FromResp for Option<T> {
+    fn from_resp(resp: Resp) -> Result<Self, FromRespError> {
+        match resp {
+            Resp::Batch(None) | Resp::MultiBatch(None) | Resp::Null => Ok(None),
+            resp => T::from_resp(resp).map(Some),
+        }
+    }
+}
+
+impl<T: FromResp> FromResp for Vec<T> {
+    fn from_resp(resp: Resp) -> Result<Self, FromRespError> {
+        items(resp, "MultiBatch")?.into_iter().map(T::from_resp).collect()
+    }
+}
+
+impl<K: FromResp + Eq + Hash, V: FromResp> FromResp for HashMap<K, V> {
+    fn from_resp(resp: Resp) -> Result<Self, FromRespError> {
+        match resp {
+            Resp::Map(pairs) => pairs.into_iter().map(|(k, v)| Ok((K::from_resp(k)?, V::from_resp(v)?))).collect(),
+            // RESP2 中 HGETALL 等命令以 key value 交替的数组返回
+            resp => {
+                let items = items(resp, "Map")?;
+                if items.len() % 2 != 0 {
+                    return Err(FromRespError::Unexpected { expected: "Map", got: "MultiBatch of odd length" });
+                }
+                let mut items = items.into_iter();
+                let mut map = HashMap::new();
+                while let (Some(k), Some(v)) = (items.next(), items.next()) {
+                    map.insert(K::from_resp(k)?, V::from_resp(v)?);
+                }
+                Ok(map)
+            }
+        }
+    }
+}
+
+macro_rules! tuple_from_resp {
+    ($len:expr, $expected:expr, $($name:ident),+) => {
+        impl<$($name: FromResp),+> FromResp for ($($name,)+) {
+            fn from_resp(resp: Resp) -> Result<Self, FromRespError> {
+                let items = items(resp, $expected)?;
+                if items.len() != $len {
+                    return Err(FromRespError::Unexpected { expected: $expected, got: "MultiBatch of other length" });
+                }
+                let mut items = items.into_iter();
+                Ok(($($name::from_resp(items.next().unwrap())?,)+))
+            }
+        }
+    };
+}
+
+tuple_from_resp!(1, "MultiBatch of 1 item", A);
+tuple_from_resp!(2, "MultiBatch of 2 items", A, B);
+tuple_from_resp!(3, "MultiBatch of 3 items", A, B, C);
+tuple_from_resp!(4, "MultiBatch of 4 items", A, B, C, D);
+
+
+#[cfg(test)]
+mod test {
+    use crate::nom::redis::from_resp::*;
+
+    fn batch(s: &str) -> Resp {
+        Resp::Batch(Some(Bytes::from(s.to_string())))
+    }
+
+    #[test]
+    fn test_scalar() {
+        assert_eq!(i64::from_resp(Resp::Int(42)), Ok(42));
+        assert_eq!(i64::from_resp(batch("-7")), Ok(-7));
+        assert_eq!(u64::from_resp(batch("18446744073709551615")), Ok(u64::MAX));
+        assert!(u64::from_resp(Resp::Int(-1)).is_err());
+        assert_eq!(f64::from_resp(batch("1.5")), Ok(1.5));
+        assert_eq!(f64::from_resp(Resp::Double(2.5)), Ok(2.5));
+        assert_eq!(bool::from_resp(Resp::Int(1)), Ok(true));
+        assert_eq!(bool::from_resp(Resp::Boolean(false)), Ok(false));
+        assert_eq!(String::from_resp(Resp::StringLine("OK".to_string())), Ok("OK".to_string()));
+        assert_eq!(String::from_resp(batch("value")), Ok("value".to_string()));
+        assert_eq!(Vec::<u8>::from_resp(Resp::Batch(Some(Bytes::from_static(b"\xff\x00")))), Ok(vec![0xff, 0x00]));
+    }
+
+    #[test]
+    fn test_error_message() {
+        let err = i64::from_resp(batch("abc")).unwrap_err();
+        assert_eq!(err.to_string(), "expected Int, got Batch");
+
+        let err = String::from_resp(Resp::Batch(Some(Bytes::from_static(b"\xff")))).unwrap_err();
+        assert_eq!(err.to_string(), "expected UTF-8 string, got Batch");
+
+        let err = Vec::<String>::from_resp(Resp::Int(1)).unwrap_err();
+        assert_eq!(err.to_string(), "expected MultiBatch, got Int");
+
+        let err = i64::from_resp(Resp::Err(RedisError::WrongType("Operation against a key".to_string()))).unwrap_err();
+        assert_eq!(err, FromRespError::Server(RedisError::WrongType("Operation against a key".to_string())));
+        assert_eq!(err.to_string(), "server error: WRONGTYPE Operation against a key");
+    }
+
+    #[test]
+    fn test_option_and_vec() {
+        assert_eq!(Option::<String>::from_resp(Resp::Batch(None)), Ok(None));
+        assert_eq!(Option::<String>::from_resp(Resp::Null), Ok(None));
+        assert_eq!(Option::<String>::from_resp(batch("a")), Ok(Some("a".to_string())));
+
+        let lrange = Resp::MultiBatch(Some(vec![batch("a"), batch("b")]));
+        assert_eq!(Vec::<String>::from_resp(lrange), Ok(vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(Vec::<String>::from_resp(Resp::MultiBatch(None)), Ok(vec![]));
+
+        let mget = Resp::MultiBatch(Some(vec![batch("1"), Resp::Batch(None)]));
+        assert_eq!(Vec::<Option<i64>>::from_resp(mget), Ok(vec![Some(1), None]));
+    }
+
+    #[test]
+    fn test_tuple_and_map() {
+        let scan = Resp::MultiBatch(Some(vec![batch("17"), Resp::MultiBatch(Some(vec![batch("k1"), batch("k2")]))]));
+        assert_eq!(
+            <(i64, Vec<String>)>::from_resp(scan),
+            Ok((17, vec!["k1".to_string(), "k2".to_string()]))
+        );
+        assert!(<(i64, i64)>::from_resp(Resp::MultiBatch(Some(vec![Resp::Int(1)]))).is_err());
+
+        let hgetall = Resp::MultiBatch(Some(vec![batch("a"), batch("1"), batch("b"), batch("2")]));
+        let map = HashMap::<String, i64>::from_resp(hgetall).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["a"], 1);
+        assert_eq!(map["b"], 2);
+
+        let resp3 = Resp::Map(vec![(Resp::StringLine("a".to_string()), Resp::Int(1))]);
+        assert_eq!(HashMap::<String, i64>::from_resp(resp3).unwrap()["a"], 1);
+
+        assert!(HashMap::<String, i64>::from_resp(Resp::MultiBatch(Some(vec![batch("a")]))).is_err());
+    }
+}
@@ -1,36 +1,240 @@
-
-
 use std::error::Error;
+use std::io::{IsTerminal, Write};
 
-use bytes::{BufMut, BytesMut};
+use bytes::Bytes;
+use futures_util::StreamExt;
 use structopt::StructOpt;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::nom::redis::command::{self, CmdBuilder};
+use crate::nom::redis::connection::Connection;
+use crate::nom::redis::format::{format_reply, OutputMode};
+use crate::nom::redis::from_resp::FromResp;
+use crate::nom::redis::options::ConnectOptions;
+use crate::nom::redis::repl::repl;
+use crate::nom::redis::pubsub::Subscriber;
+use crate::nom::redis::resp::Resp;
+use crate::nom::redis::script::Script;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "parser_toy", about = "a tiny redis-cli built on the nom RESP parser")]
+struct Cli {
+    #[structopt(flatten)]
+    connection: ConnectOptions,
+
+    /// print raw replies without type hints and quoting
+    #[structopt(long, conflicts_with = "json")]
+    raw: bool,
+
+    /// print replies as JSON
+    #[structopt(long)]
+    json: bool,
+
+    /// run a Lua script: --eval file.lua key1 key2 , arg1 arg2
+    #[structopt(long, value_name = "file", min_values = 1, allow_hyphen_values = true)]
+    eval: Option<Vec<String>>,
 
-use crate::nom::redis::{command, resp};
+    /// command to run; without one, starts an interactive prompt, or runs the
+    /// commands read line by line from stdin when it is not a terminal
+    #[structopt(subcommand)]
+    command: Option<command::Commands>,
+}
+
+impl Cli {
+    fn output_mode(&self) -> OutputMode {
+        if self.raw {
+            OutputMode::Raw
+        } else if self.json {
+            OutputMode::Json
+        } else {
+            OutputMode::Default
+        }
+    }
+}
 
 
 pub async fn redis_cli() -> Result<(), Box<dyn Error>> {
     pretty_env_logger::init();
     info!("redis-cli start");
 
+    let cli = Cli::from_args();
+    let command = match (&cli.command, &cli.eval) {
+        (Some(command), None) => command.validate().map(|_| Some(command)),
+        (None, _) => Ok(None),
+        (Some(_), Some(_)) => Err("--eval can not be combined with a subcommand".to_string()),
+    };
+    let command = command.unwrap_or_else(|message| {
+        structopt::clap::Error::with_description(&message, structopt::clap::ErrorKind::ValueValidation).exit()
+    });
 
-    let mut stream = TcpStream::connect("127.0.0.1:6379").await?;
-    let mut buf = [0u8; 1024];
-    let mut resp = BytesMut::with_capacity(1024);
+    let mut conn = match Connection::open(&cli.connection).await {
+        Ok(conn) => conn,
+        Err(e) => {
+            eprintln!("Could not connect to Redis: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-    let (mut reader, mut writer) = stream.split();
-    // ping
-    // writer.write(b"*1\r\n$4\r\nPING\r\n").await?;
+    let command = match command {
+        Some(command) => command,
+        None => match &cli.eval {
+            Some(eval_args) => return eval(&mut conn, eval_args, cli.output_mode()).await,
+            None if std::io::stdin().is_terminal() => {
+                return repl(&mut conn, &cli.connection.resolve()?, cli.output_mode()).await;
+            }
+            None => return run_lines(&mut conn, cli.output_mode()).await,
+        },
+    };
+    match command {
+        command::Commands::Scan { .. } => return scan(&mut conn, command.clone(), cli.output_mode(), &mut std::io::stdout()).await,
+        command::Commands::Subscribe { .. } | command::Commands::Psubscribe { .. } => {
+            return subscribe(conn, command, cli.output_mode()).await;
+        }
+        _ => {}
+    }
 
-    // command
-    let com = command::Commands::from_args();
-    writer.write(&com.to_bytes()).await?;
+    let reply = conn.request(command).await?;
+    std::io::stdout().write_all(&format_reply(&reply, cli.output_mode()))?;
+    Ok(())
+}
 
-    // read
-    let n = reader.read(&mut buf).await?;
-    resp.put(&buf[0..n]);
-    let reply = resp::Resp::from_resp(&resp);
-    println!("{}", reply);
+// eval 读取脚本文件并执行，参数与 redis-cli 相同：逗号之前为 key，之后为 arg
+async fn eval(conn: &mut Connection, eval: &[String], mode: OutputMode) -> Result<(), Box<dyn Error>> {
+    let (file, rest) = eval.split_first().ok_or("--eval requires a script file")?;
+    let script = Script::new(std::fs::read_to_string(file)?);
+    let (keys, args) = match rest.iter().position(|arg| arg == ",") {
+        Some(comma) => (&rest[..comma], &rest[comma + 1..]),
+        None => (rest, &[][..]),
+    };
+    let reply = script.invoke(conn, keys, args).await?;
+    std::io::stdout().write_all(&format_reply(&reply, mode))?;
     Ok(())
 }
+
+// run_lines 与 redis-cli < commands.txt 相同，逐行执行标准输入中的命令，跳过空行；
+// 无法拆分的行在标准错误中报告后跳过
+async fn run_lines(conn: &mut Connection, mode: OutputMode) -> Result<(), Box<dyn Error>> {
+    let mut stdout = std::io::stdout();
+    for (n, line) in std::io::stdin().lines().enumerate() {
+        let builder = match CmdBuilder::from_line(&line?) {
+            Ok(builder) if builder.is_empty() => continue,
+            Ok(builder) => builder,
+            Err(_) => {
+                eprintln!("line {}: Invalid argument(s)", n + 1);
+                continue;
+            }
+        };
+        conn.write_all(&builder.to_bytes()).await?;
+        stdout.write_all(&format_reply(&conn.read_reply().await?, mode))?;
+    }
+    Ok(())
+}
+
+// scan 循环发送 SCAN 直到游标回到 0，每批 key 收到后立即写到 stdout
+async fn scan<S, W>(
+    conn: &mut Connection<S>,
+    mut command: command::Commands,
+    mode: OutputMode,
+    stdout: &mut W,
+) -> Result<(), Box<dyn Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    W: Write,
+{
+    // 与 redis-cli --scan 一样每行输出一个 key，JSON 模式下每行一个 JSON 字符串
+    let mode = if mode == OutputMode::Json { OutputMode::Json } else { OutputMode::Raw };
+    loop {
+        let (next, keys) = match conn.request(&command).await? {
+            // 与 redis-cli 一样输出服务端的错误，以非零状态退出
+            reply @ Resp::Err(_) => {
+                stdout.write_all(&format_reply(&reply, mode))?;
+                stdout.flush()?;
+                std::process::exit(1);
+            }
+            reply => match <(u64, Vec<Bytes>)>::from_resp(reply) {
+                Ok(reply) => reply,
+                Err(e) => {
+                    eprintln!("unexpected SCAN reply: {}", e);
+                    std::process::exit(1);
+                }
+            },
+        };
+        for key in keys {
+            stdout.write_all(&format_reply(&Resp::Batch(Some(key)), mode))?;
+        }
+        stdout.flush()?;
+
+        if next == 0 {
+            return Ok(());
+        }
+        if let command::Commands::Scan { cursor, .. } = &mut command {
+            *cursor = next;
+        }
+    }
+}
+
+// subscribe 进入订阅模式，持续输出收到的消息，直到 Ctrl-C 或服务端关闭连接；读取出错时以非零状态退出
+async fn subscribe(conn: Connection, command: &command::Commands, mode: OutputMode) -> Result<(), Box<dyn Error>> {
+    let mut subscriber = Subscriber::new(conn);
+    match command {
+        command::Commands::Subscribe { channels } => subscriber.subscribe(channels).await?,
+        command::Commands::Psubscribe { patterns } => subscriber.psubscribe(patterns).await?,
+        _ => unreachable!("not a subscribe command"),
+    }
+
+    let mut stdout = std::io::stdout();
+    let messages = subscriber.messages();
+    tokio::pin!(messages);
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+            message = messages.next() => match message {
+                Some(Ok(message)) => {
+                    stdout.write_all(&format_reply(&Resp::from(message), mode))?;
+                    stdout.flush()?;
+                }
+                Some(Err(e)) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+                None => return Ok(()),
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::nom::redis::main::*;
+
+    #[tokio::test]
+    async fn test_scan() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let server = tokio::spawn(async move {
+            // 第一页返回游标 17，第二页按游标 17 继续并返回 0
+            let pages = [
+                (&b"*4\r\n$4\r\nSCAN\r\n$1\r\n0\r\n$5\r\nCOUNT\r\n$1\r\n2\r\n"[..], &b"*2\r\n$2\r\n17\r\n*2\r\n$1\r\na\r\n$1\r\nb\r\n"[..]),
+                (b"*4\r\n$4\r\nSCAN\r\n$2\r\n17\r\n$5\r\nCOUNT\r\n$1\r\n2\r\n", b"*2\r\n$1\r\n0\r\n*1\r\n$1\r\nc\r\n"),
+            ];
+            for (expected, reply) in pages {
+                let mut request = vec![0; expected.len()];
+                server.read_exact(&mut request).await.unwrap();
+                assert_eq!(&request[..], expected);
+                server.write_all(reply).await.unwrap();
+            }
+            // 游标回到 0 之后不再发送请求
+            assert_eq!(server.read(&mut [0; 1]).await.unwrap(), 0);
+        });
+
+        let mut conn = Connection::new(client);
+        let command = command::Commands::from_iter_safe(["parser_toy", "scan", "--count", "2"]).unwrap();
+        let mut out = Vec::new();
+        scan(&mut conn, command, OutputMode::Raw, &mut out).await.unwrap();
+        drop(conn);
+        server.await.unwrap();
+        assert_eq!(out, b"a\nb\nc\n");
+    }
+}
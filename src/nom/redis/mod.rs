@@ -1,4 +1,18 @@
 pub mod main;
 pub mod command;
 
-pub mod resp;
\ No newline at end of file
+pub mod resp;
+pub mod error;
+pub mod decoder;
+pub mod from_resp;
+pub mod format;
+pub mod request;
+pub mod args;
+pub mod resp_ref;
+pub mod connection;
+pub mod transaction;
+pub mod pubsub;
+pub mod script;
+pub mod stream;
+pub mod options;
+pub mod repl;
\ No newline at end of file
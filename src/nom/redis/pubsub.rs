@@ -0,0 +1,214 @@
+use std::io;
+
+use bytes::Bytes;
+use futures_util::stream::{self, Stream};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+
+use crate::nom::redis::command::{CmdBuilder, ToArg};
+use crate::nom::redis::connection::Connection;
+use crate::nom::redis::from_resp::{FromResp, FromRespError};
+use crate::nom::redis::resp::Resp;
+
+/// Message received by a connection in subscriber mode.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PubSubMessage {
+    /// SUBSCRIBE acknowledgement, with the number of active subscriptions.
+    Subscribe { channel: Bytes, count: i64 },
+    /// UNSUBSCRIBE acknowledgement; `channel` is `None` when there was
+    /// nothing to unsubscribe from.
+    Unsubscribe { channel: Option<Bytes>, count: i64 },
+    /// PSUBSCRIBE acknowledgement.
+    PSubscribe { pattern: Bytes, count: i64 },
+    /// PUNSUBSCRIBE acknowledgement.
+    PUnsubscribe { pattern: Option<Bytes>, count: i64 },
+    /// A message published to a subscribed channel.
+    Message { channel: Bytes, payload: Bytes },
+    /// A message published to a channel matching a subscribed pattern.
+    PMessage { pattern: Bytes, channel: Bytes, payload: Bytes },
+}
+
+impl FromResp for PubSubMessage {
+    // RESP2 中为数组，RESP3 中为 push 类型，元素相同
+    fn from_resp(resp: Resp) -> Result<Self, FromRespError> {
+        let mut items = Vec::<Resp>::from_resp(resp)?.into_iter();
+        let mut next = || items.next().ok_or(FromRespError::Unexpected {
+            expected: "pub/sub message",
+            got: "MultiBatch of other length",
+        });
+        let kind = String::from_resp(next()?)?;
+        let message = match kind.as_str() {
+            "subscribe" => PubSubMessage::Subscribe { channel: FromResp::from_resp(next()?)?, count: FromResp::from_resp(next()?)? },
+            "unsubscribe" => PubSubMessage::Unsubscribe { channel: FromResp::from_resp(next()?)?, count: FromResp::from_resp(next()?)? },
+            "psubscribe" => PubSubMessage::PSubscribe { pattern: FromResp::from_resp(next()?)?, count: FromResp::from_resp(next()?)? },
+            "punsubscribe" => PubSubMessage::PUnsubscribe { pattern: FromResp::from_resp(next()?)?, count: FromResp::from_resp(next()?)? },
+            "message" => PubSubMessage::Message { channel: FromResp::from_resp(next()?)?, payload: FromResp::from_resp(next()?)? },
+            "pmessage" => PubSubMessage::PMessage {
+                pattern: FromResp::from_resp(next()?)?,
+                channel: FromResp::from_resp(next()?)?,
+                payload: FromResp::from_resp(next()?)?,
+            },
+            _ => return Err(FromRespError::Unexpected { expected: "pub/sub message", got: "unknown message kind" }),
+        };
+        Ok(message)
+    }
+}
+
+impl From<PubSubMessage> for Resp {
+    // 还原为服务端发送的 RESP2 数组
+    fn from(message: PubSubMessage) -> Self {
+        let kind = |kind: &str| Resp::Batch(Some(Bytes::from(kind.to_string())));
+        let items = match message {
+            PubSubMessage::Subscribe { channel, count } => vec![kind("subscribe"), Resp::Batch(Some(channel)), Resp::Int(count)],
+            PubSubMessage::Unsubscribe { channel, count } => vec![kind("unsubscribe"), Resp::Batch(channel), Resp::Int(count)],
+            PubSubMessage::PSubscribe { pattern, count } => vec![kind("psubscribe"), Resp::Batch(Some(pattern)), Resp::Int(count)],
+            PubSubMessage::PUnsubscribe { pattern, count } => vec![kind("punsubscribe"), Resp::Batch(pattern), Resp::Int(count)],
+            PubSubMessage::Message { channel, payload } => {
+                vec![kind("message"), Resp::Batch(Some(channel)), Resp::Batch(Some(payload))]
+            }
+            PubSubMessage::PMessage { pattern, channel, payload } => {
+                vec![kind("pmessage"), Resp::Batch(Some(pattern)), Resp::Batch(Some(channel)), Resp::Batch(Some(payload))]
+            }
+        };
+        Resp::MultiBatch(Some(items))
+    }
+}
+
+/// Connection in subscriber mode.
+///
+/// Subscription commands only write the request; their acknowledgements
+/// arrive as messages, in order with the published messages.
+#[derive(Debug)]
+pub struct Subscriber<S = TcpStream> {
+    conn: Connection<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Subscriber<S> {
+    pub fn new(conn: Connection<S>) -> Self {
+        Subscriber { conn }
+    }
+
+    pub async fn subscribe<T: ToArg>(&mut self, channels: &[T]) -> io::Result<()> {
+        self.send("SUBSCRIBE", channels).await
+    }
+
+    pub async fn psubscribe<T: ToArg>(&mut self, patterns: &[T]) -> io::Result<()> {
+        self.send("PSUBSCRIBE", patterns).await
+    }
+
+    /// Unsubscribe from `channels`, or from every channel when empty.
+    pub async fn unsubscribe<T: ToArg>(&mut self, channels: &[T]) -> io::Result<()> {
+        self.send("UNSUBSCRIBE", channels).await
+    }
+
+    /// Unsubscribe from `patterns`, or from every pattern when empty.
+    pub async fn punsubscribe<T: ToArg>(&mut self, patterns: &[T]) -> io::Result<()> {
+        self.send("PUNSUBSCRIBE", patterns).await
+    }
+
+    async fn send<T: ToArg>(&mut self, command: &str, names: &[T]) -> io::Result<()> {
+        let mut builder = CmdBuilder::new().arg(command);
+        names.iter().for_each(|name| builder.add_arg(name));
+        self.conn.write_all(&builder.to_bytes()).await
+    }
+
+    /// Wait for the next message.
+    pub async fn next_message(&mut self) -> io::Result<PubSubMessage> {
+        let reply = self.conn.read_reply().await?;
+        PubSubMessage::from_resp(reply).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Messages as an async stream. It ends when the server closes the
+    /// connection, or after the first error.
+    pub fn messages(&mut self) -> impl Stream<Item = io::Result<PubSubMessage>> + '_ {
+        stream::unfold(Some(self), |subscriber| async move {
+            let subscriber = subscriber?;
+            let message = match subscriber.conn.next_reply().await {
+                Ok(Some(reply)) => PubSubMessage::from_resp(reply).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+                Ok(None) => return None,
+                Err(e) => Err(e),
+            };
+            match message {
+                Ok(message) => Some((Ok(message), Some(subscriber))),
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+
+    /// Leave subscriber mode handling and get the connection back.
+    pub fn into_inner(self) -> Connection<S> {
+        self.conn
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use futures_util::StreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::nom::redis::pubsub::*;
+    use crate::nom::redis::resp::parse;
+
+    fn bytes(s: &str) -> Bytes {
+        Bytes::from(s.to_string())
+    }
+
+    #[test]
+    fn test_from_resp() {
+        let cases: [(&[u8], PubSubMessage); 4] = [
+            (b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n", PubSubMessage::Subscribe { channel: bytes("news"), count: 1 }),
+            (b"*3\r\n$11\r\nunsubscribe\r\n$-1\r\n:0\r\n", PubSubMessage::Unsubscribe { channel: None, count: 0 }),
+            (b">3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$2\r\nhi\r\n", PubSubMessage::Message { channel: bytes("news"), payload: bytes("hi") }),
+            (
+                b"*4\r\n$8\r\npmessage\r\n$2\r\nn*\r\n$4\r\nnews\r\n$2\r\nhi\r\n",
+                PubSubMessage::PMessage { pattern: bytes("n*"), channel: bytes("news"), payload: bytes("hi") },
+            ),
+        ];
+        for (src, message) in cases {
+            let (_, resp) = parse(src).unwrap();
+            assert_eq!(PubSubMessage::from_resp(resp), Ok(message.clone()));
+            assert_eq!(PubSubMessage::from_resp(Resp::from(message.clone())), Ok(message));
+        }
+
+        assert!(PubSubMessage::from_resp(Resp::StringLine("PONG".to_string())).is_err());
+        assert!(PubSubMessage::from_resp(Resp::MultiBatch(Some(vec![Resp::Batch(Some(bytes("message")))]))).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_messages() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let server = tokio::spawn(async move {
+            let expected = b"*3\r\n$9\r\nSUBSCRIBE\r\n$1\r\na\r\n$1\r\nb\r\n";
+            let mut request = vec![0; expected.len()];
+            server.read_exact(&mut request).await.unwrap();
+            assert_eq!(&request[..], &expected[..]);
+            server.write_all(b"*3\r\n$9\r\nsubscribe\r\n$1\r\na\r\n:1\r\n*3\r\n$9\r\nsubscribe\r\n$1\r\nb\r\n:2\r\n").await.unwrap();
+            server.write_all(b"*3\r\n$7\r\nmessage\r\n$1\r\nb\r\n$5\r\nhello\r\n").await.unwrap();
+        });
+
+        let mut subscriber = Subscriber::new(Connection::new(client));
+        subscriber.subscribe(&[bytes("a"), bytes("b")]).await.unwrap();
+        let messages: Vec<_> = subscriber.messages().collect().await;
+        server.await.unwrap();
+
+        // 连接在两条消息之间关闭时流正常结束
+        assert_eq!(messages.into_iter().map(Result::unwrap).collect::<Vec<_>>(), vec![
+            PubSubMessage::Subscribe { channel: bytes("a"), count: 1 },
+            PubSubMessage::Subscribe { channel: bytes("b"), count: 2 },
+            PubSubMessage::Message { channel: bytes("b"), payload: bytes("hello") },
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_messages_truncated() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        server.write_all(b"*3\r\n$7\r\nmessage\r\n$1\r\nb\r\n$5\r\nhel").await.unwrap();
+        drop(server);
+
+        let mut subscriber = Subscriber::new(Connection::new(client));
+        let messages: Vec<_> = subscriber.messages().collect().await;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].as_ref().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,226 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Write;
+use std::path::PathBuf;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use structopt::StructOpt;
+
+use crate::nom::redis::args::split_args;
+use crate::nom::redis::command::{CmdBuilder, Commands};
+use crate::nom::redis::connection::Connection;
+use crate::nom::redis::format::{format_reply, OutputMode};
+use crate::nom::redis::options::ConnectOptions;
+use crate::nom::redis::resp::Resp;
+
+/// Line editor helper that shows the remaining arguments of the commands
+/// known by [`Commands`] after the cursor, e.g. `SET k` hints ` value [x]`.
+#[derive(Debug, Default)]
+pub struct CommandHinter {
+    // 命令名到参数列表的缓存，未知命令为空列表
+    params: RefCell<HashMap<String, Vec<String>>>,
+}
+
+impl CommandHinter {
+    pub fn new() -> Self {
+        CommandHinter::default()
+    }
+
+    /// Positional arguments of `command`, empty for unknown commands.
+    pub fn params(&self, command: &str) -> Vec<String> {
+        let command = command.to_lowercase();
+        self.params.borrow_mut().entry(command).or_insert_with_key(|command| usage_params(command)).clone()
+    }
+}
+
+// usage_params 从 structopt 生成的帮助中的 USAGE 行取出位置参数，如 `<key> <value> [x]`；
+// 直接查找子命令而不是解析参数，避免 --version 之类的输入被 clap 当作全局选项处理
+fn usage_params(command: &str) -> Vec<String> {
+    if command.starts_with('-') {
+        return vec![];
+    }
+    let app = Commands::clap();
+    let subcommand = match app.p.subcommands.iter().find(|subcommand| subcommand.get_name() == command) {
+        Some(subcommand) => subcommand,
+        None => return vec![],
+    };
+    let mut help = Vec::new();
+    if subcommand.write_help(&mut help).is_err() {
+        return vec![];
+    }
+    let help = String::from_utf8_lossy(&help);
+    let usage = help.lines().skip_while(|line| !line.starts_with("USAGE:")).nth(1).unwrap_or_default();
+    usage
+        .split_whitespace()
+        .skip(1)
+        .filter(|param| !matches!(*param, "[FLAGS]" | "[OPTIONS]" | "<SUBCOMMAND>"))
+        .map(|param| param.replace(['<', '>'], ""))
+        .collect()
+}
+
+impl Hinter for CommandHinter {
+    type Hint = String;
+
+    // 光标在行尾时提示尚未输入的参数，可变参数一直保留
+    fn hint(&self, line: &str, pos: usize, _: &Context<'_>) -> Option<String> {
+        if pos < line.len() {
+            return None;
+        }
+        let argv = split_args(line.as_bytes()).ok()?;
+        let command = std::str::from_utf8(argv.first()?).ok()?;
+        let typed = argv.len() - 1;
+        let params = self.params(command);
+        let remaining: Vec<_> = params
+            .iter()
+            .enumerate()
+            .filter(|(i, param)| *i >= typed || param.ends_with("..."))
+            .map(|(_, param)| param.as_str())
+            .collect();
+        if remaining.is_empty() {
+            return None;
+        }
+        let separator = if line.ends_with(char::is_whitespace) { "" } else { " " };
+        Some(format!("{}{}", separator, remaining.join(" ")))
+    }
+}
+
+impl Highlighter for CommandHinter {
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("\x1b[90m{}\x1b[0m", hint))
+    }
+}
+
+impl Completer for CommandHinter {
+    type Candidate = String;
+}
+
+impl Validator for CommandHinter {}
+
+impl Helper for CommandHinter {}
+
+// history_path 历史记录文件 ~/.parser_toy_history
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".parser_toy_history"))
+}
+
+// prompt 与 redis-cli 相同，如 127.0.0.1:6379[2]>
+fn prompt(options: &ConnectOptions, db: u32) -> String {
+    match db {
+        0 => format!("{}:{}> ", options.host(), options.port()),
+        db => format!("{}:{}[{}]> ", options.host(), options.port(), db),
+    }
+}
+
+/// Interactive prompt on one open connection: each line is split like in
+/// redis-cli, sent as is and its reply printed. `quit`, `exit`, Ctrl-C and
+/// Ctrl-D leave the prompt.
+pub async fn repl(conn: &mut Connection, options: &ConnectOptions, mode: OutputMode) -> Result<(), Box<dyn Error>> {
+    let mut editor = Editor::<CommandHinter, DefaultHistory>::new()?;
+    editor.set_helper(Some(CommandHinter::new()));
+    let history = history_path();
+    if let Some(history) = &history {
+        // 首次运行时文件不存在
+        let _ = editor.load_history(history);
+    }
+
+    // 连接断开等错误返回之前也要保存历史记录
+    let result = read_eval_print(&mut editor, conn, options, mode).await;
+    if let Some(history) = &history {
+        if let Err(e) = editor.save_history(history) {
+            warn!("can not save history to {}: {}", history.display(), e);
+        }
+    }
+    result
+}
+
+// read_eval_print 逐行读取、执行并输出回复，直到用户退出或出错
+async fn read_eval_print(
+    editor: &mut Editor<CommandHinter, DefaultHistory>,
+    conn: &mut Connection,
+    options: &ConnectOptions,
+    mode: OutputMode,
+) -> Result<(), Box<dyn Error>> {
+    let mut db = options.db.unwrap_or_default();
+    let mut stdout = std::io::stdout();
+    loop {
+        let line = match editor.readline(&prompt(options, db)) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let argv = match split_args(line.as_bytes()) {
+            Ok(argv) if argv.is_empty() => continue,
+            Ok(argv) => argv,
+            Err(_) => {
+                println!("Invalid argument(s)");
+                continue;
+            }
+        };
+        editor.add_history_entry(line.as_str())?;
+
+        let command = String::from_utf8_lossy(&argv[0]).to_lowercase();
+        match command.as_str() {
+            "quit" | "exit" => break,
+            "subscribe" | "psubscribe" => {
+                println!("(error) use the {} subcommand to listen for messages", command);
+                continue;
+            }
+            _ => {}
+        }
+
+        let mut builder = CmdBuilder::new();
+        argv.iter().for_each(|arg| builder.add_arg(arg));
+        conn.write_all(&builder.to_bytes()).await?;
+        let reply = conn.read_reply().await?;
+        if command == "select" && matches!(&reply, Resp::StringLine(ok) if ok == "OK") {
+            db = std::str::from_utf8(&argv[1]).ok().and_then(|db| db.parse().ok()).unwrap_or(db);
+        }
+        stdout.write_all(&format_reply(&reply, mode))?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod test {
+    use rustyline::history::DefaultHistory;
+
+    use crate::nom::redis::repl::*;
+
+    #[test]
+    fn test_params() {
+        let hinter = CommandHinter::new();
+        assert_eq!(hinter.params("SET"), vec!["key", "value", "[x]"]);
+        assert_eq!(hinter.params("zadd"), vec!["key", "score-members..."]);
+        assert_eq!(hinter.params("ping"), Vec::<String>::new());
+        assert_eq!(hinter.params("nosuchcommand"), Vec::<String>::new());
+        // 选项不会被当作命令交给 clap，也就不会输出版本号
+        assert_eq!(hinter.params("--version"), Vec::<String>::new());
+        assert_eq!(hinter.params("-V"), Vec::<String>::new());
+        assert_eq!(hinter.params("help"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_hint() {
+        let hinter = CommandHinter::new();
+        let history = DefaultHistory::new();
+        let ctx = Context::new(&history);
+        let hint = |line: &str| hinter.hint(line, line.len(), &ctx);
+        assert_eq!(hint("set"), Some(" key value [x]".to_string()));
+        assert_eq!(hint("SET "), Some("key value [x]".to_string()));
+        assert_eq!(hint("set \"my key\""), Some(" value [x]".to_string()));
+        assert_eq!(hint("set k v nx"), None);
+        assert_eq!(hint("zadd board 1 a"), Some(" score-members...".to_string()));
+        assert_eq!(hint("set \"k"), None);
+        assert_eq!(hinter.hint("set", 1, &ctx), None);
+    }
+}
@@ -0,0 +1,259 @@
+use bytes::{Bytes, BytesMut};
+use nom::branch::alt;
+use nom::bytes::streaming::take_while;
+use nom::character::streaming::char;
+use nom::combinator::cut;
+use nom::sequence::{preceded, terminated};
+
+use crate::nom::redis::args;
+use crate::nom::redis::error::{RespError, RespErrorKind, RespParseError};
+use crate::nom::redis::resp::{self, RespLimits, RespResult};
+
+/// Parse one client request into its argv.
+///
+/// Both forms sent by clients are accepted: multibulk requests
+/// (`*2\r\n$3\r\nGET\r\n$1\r\na\r\n`, as written by `CmdBuilder`) and inline
+/// commands typed into telnet (`SET a "b c"\r\n`). An empty inline line gives
+/// an empty argv, which servers should skip.
+pub fn parse_request(i: &[u8]) -> RespResult<'_, Vec<Bytes>> {
+    parse_request_with_limits(i, &RespLimits::default())
+}
+
+/// Parse one client request, rejecting it as soon as it exceeds `limits`.
+pub fn parse_request_with_limits<'a>(i: &'a [u8], limits: &RespLimits) -> RespResult<'a, Vec<Bytes>> {
+    resp::within_frame(i, limits, alt((|i| multibulk(i, limits), parse_inline))(i))
+}
+
+pub fn parse_multibulk(i: &[u8]) -> RespResult<'_, Vec<Bytes>> {
+    multibulk(i, &RespLimits::default())
+}
+
+fn multibulk<'a>(i: &'a [u8], limits: &RespLimits) -> RespResult<'a, Vec<Bytes>> {
+    let (mut i, count) = multibulk_header(i, limits)?;
+    let mut argv = Vec::new();
+    for _ in 0..count {
+        let (remain, arg) = multibulk_arg(i, limits)?;
+        argv.push(arg);
+        i = remain;
+    }
+    Ok((i, argv))
+}
+
+// multibulk_header 读取 *N 行，返回参数个数，负数按 0 处理
+fn multibulk_header<'a>(i: &'a [u8], limits: &RespLimits) -> RespResult<'a, usize> {
+    let (i, count) = preceded(char('*'), cut(|i| resp::array_len(i, limits)))(i)?;
+    Ok((i, count.max(0) as usize))
+}
+
+// multibulk_arg 读取一个 $ 参数，请求中的参数不能是空 bulk
+fn multibulk_arg<'a>(i: &'a [u8], limits: &RespLimits) -> RespResult<'a, Bytes> {
+    match preceded(char('$'), cut(|i| resp::bulk(i, limits)))(i) {
+        Ok((remain, Some(arg))) => Ok((remain, Bytes::copy_from_slice(arg))),
+        Ok((_, None)) => resp::fail(&i[1..], RespErrorKind::InvalidLength),
+        Err(nom::Err::Error(e)) => Err(nom::Err::Failure(e)),
+        Err(e) => Err(e),
+    }
+}
+
+// parse_inline 读取一行（\n 结尾，可带 \r）并按 redis-cli 的规则切分参数
+pub fn parse_inline(i: &[u8]) -> RespResult<'_, Vec<Bytes>> {
+    let (remain, line) = terminated(take_while(|c: u8| c != b'\n'), char('\n'))(i)?;
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    match args::split_args(line) {
+        Ok(argv) => Ok((remain, argv)),
+        Err(_) => resp::fail(i, RespErrorKind::UnbalancedQuotes),
+    }
+}
+
+/// Stateful decoder for the requests a server reads from one connection.
+///
+/// A request that arrives in many small chunks is scanned only once: the
+/// arguments already read are kept until the request is complete.
+#[derive(Debug, Default)]
+pub struct RequestDecoder {
+    buf: BytesMut,
+    limits: RespLimits,
+    // 当前请求已读取的字节数
+    scanned: usize,
+    // 当前 multibulk 请求的参数个数，尚未读到 *N 行时为 None
+    argc: Option<usize>,
+    argv: Vec<Bytes>,
+}
+
+impl RequestDecoder {
+    pub fn new() -> Self {
+        RequestDecoder::default()
+    }
+
+    /// Create a decoder that rejects requests exceeding `limits`.
+    pub fn with_limits(limits: RespLimits) -> Self {
+        RequestDecoder { limits, ..RequestDecoder::default() }
+    }
+
+    /// Append bytes received from the client.
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Take the next complete request out of the buffer, `Ok(None)` when
+    /// more bytes are needed. Empty inline lines are skipped.
+    pub fn decode(&mut self) -> Result<Option<Vec<Bytes>>, RespError> {
+        loop {
+            match self.scan() {
+                Ok(Some(argv)) if argv.is_empty() => continue,
+                Err(err) => {
+                    self.buf.clear();
+                    self.scanned = 0;
+                    self.argc = None;
+                    self.argv.clear();
+                    return Err(err);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // scan 从上次停下的位置继续读取当前请求，读完后把它从缓冲区中取出
+    fn scan(&mut self) -> Result<Option<Vec<Bytes>>, RespError> {
+        let argc = match self.argc {
+            Some(argc) => argc,
+            None if self.buf.first() == Some(&b'*') => match self.step(multibulk_header)? {
+                Some(argc) => *self.argc.insert(argc),
+                None => return Ok(None),
+            },
+            None => return self.scan_inline(),
+        };
+        while self.argv.len() < argc {
+            match self.step(multibulk_arg)? {
+                Some(arg) => self.argv.push(arg),
+                None => return Ok(None),
+            }
+        }
+        let _ = self.buf.split_to(self.scanned);
+        self.scanned = 0;
+        self.argc = None;
+        Ok(Some(std::mem::take(&mut self.argv)))
+    }
+
+    // scan_inline 只在新收到的字节中查找换行，读到整行后再切分参数
+    fn scan_inline(&mut self) -> Result<Option<Vec<Bytes>>, RespError> {
+        let max = self.limits.max_frame_size;
+        let end = match self.buf[self.scanned..].iter().position(|&c| c == b'\n') {
+            Some(n) => self.scanned + n + 1,
+            None if self.buf.len() >= max => return Err(RespError { kind: RespErrorKind::FrameTooLarge { max }, offset: max }),
+            None => {
+                self.scanned = self.buf.len();
+                return Ok(None);
+            }
+        };
+        let line = &self.buf[..end];
+        let (_, argv) = resp::within_frame(line, &self.limits, parse_inline(line))
+            .map_err(|e| RespParseError::into_error(e, line))?;
+        let _ = self.buf.split_to(end);
+        self.scanned = 0;
+        Ok(Some(argv))
+    }
+
+    // step 在 scanned 处解析一项并前移 scanned，数据不足时返回 None
+    fn step<O>(
+        &mut self,
+        f: impl for<'a> FnOnce(&'a [u8], &RespLimits) -> RespResult<'a, O>,
+    ) -> Result<Option<O>, RespError> {
+        let max = self.limits.max_frame_size;
+        let too_large = RespError { kind: RespErrorKind::FrameTooLarge { max }, offset: max };
+        match f(&self.buf[self.scanned..], &self.limits) {
+            Ok((remain, o)) => {
+                self.scanned = self.buf.len() - remain.len();
+                if self.scanned > max {
+                    return Err(too_large);
+                }
+                Ok(Some(o))
+            }
+            Err(nom::Err::Incomplete(_)) if self.buf.len() >= max => Err(too_large),
+            Err(nom::Err::Incomplete(_)) => Ok(None),
+            Err(e) => Err(RespParseError::into_error(e, &self.buf)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nom::redis::request::*;
+
+    fn argv(args: &[&str]) -> Vec<Bytes> {
+        args.iter().map(|arg| Bytes::from(arg.to_string())).collect()
+    }
+
+    #[test]
+    fn test_parse_multibulk() {
+        let (remain, req) = parse_request(b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n$5\r\nb\r\nc!\r\n").unwrap();
+        assert!(remain.is_empty());
+        assert_eq!(req, argv(&["SET", "a", "b\r\nc!"]));
+
+        assert!(matches!(parse_request(b"*2\r\n$3\r\nGET\r\n"), Err(nom::Err::Incomplete(_))));
+        assert_eq!(
+            parse_request(b"*2\r\n$3\r\nGET\r\n:1\r\n"),
+            Err(nom::Err::Failure(RespParseError::new(b":1\r\n", RespErrorKind::InvalidPrefix(b':'))))
+        );
+        assert_eq!(
+            parse_request(b"*1\r\n$-1\r\n"),
+            Err(nom::Err::Failure(RespParseError::new(b"-1\r\n", RespErrorKind::InvalidLength)))
+        );
+    }
+
+    #[test]
+    fn test_parse_inline() {
+        assert_eq!(parse_request(b"PING\r\n").unwrap(), (&b""[..], argv(&["PING"])));
+        assert_eq!(parse_request(b"  SET a \"b c\"\n").unwrap().1, argv(&["SET", "a", "b c"]));
+        assert_eq!(parse_request(b"SET a \"x\\\"y\\n\" \"\"\r\n").unwrap().1, argv(&["SET", "a", "x\"y\n", ""]));
+        assert_eq!(parse_request(b"\r\n").unwrap().1, argv(&[]));
+        assert!(matches!(parse_request(b"GET a"), Err(nom::Err::Incomplete(_))));
+        assert_eq!(
+            parse_request(b"SET a \"b c\r\n"),
+            Err(nom::Err::Failure(RespParseError::new(b"SET a \"b c\r\n", RespErrorKind::UnbalancedQuotes)))
+        );
+        assert!(parse_request(b"SET a \"b\"c\r\n").is_err());
+    }
+
+    #[test]
+    fn test_request_decoder() {
+        let mut decoder = RequestDecoder::new();
+        decoder.feed(b"PING\r\n\r\n*2\r\n$3\r\nGET\r\n$1\r");
+        assert_eq!(decoder.decode(), Ok(Some(argv(&["PING"]))));
+        assert_eq!(decoder.decode(), Ok(None));
+        decoder.feed(b"\na\r\n");
+        assert_eq!(decoder.decode(), Ok(Some(argv(&["GET", "a"]))));
+        assert_eq!(decoder.decode(), Ok(None));
+
+        decoder.feed(b"*1\r\n+PING\r\n");
+        assert_eq!(decoder.decode(), Err(RespError { kind: RespErrorKind::InvalidPrefix(b'+'), offset: 4 }));
+
+        let mut decoder = RequestDecoder::with_limits(RespLimits { max_frame_size: 16, ..RespLimits::default() });
+        decoder.feed(b"SET key 0123456789");
+        assert_eq!(decoder.decode(), Err(RespError { kind: RespErrorKind::FrameTooLarge { max: 16 }, offset: 16 }));
+    }
+
+    #[test]
+    fn test_request_decoder_resume() {
+        // 逐字节送入，已读完的参数不会被重复解析
+        let value = "v".repeat(1000);
+        let mut decoder = RequestDecoder::new();
+        let request = [&b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1000\r\n"[..], value.as_bytes(), b"\r\nPING\r\n"].concat();
+        let mut decoded = Vec::new();
+        for &b in &request {
+            decoder.feed(&[b]);
+            while let Some(argv) = decoder.decode().unwrap() {
+                decoded.push(argv);
+            }
+        }
+        assert_eq!(decoded, vec![argv(&["SET", "k", &value]), argv(&["PING"])]);
+
+        let mut decoder = RequestDecoder::with_limits(RespLimits { max_frame_size: 16, ..RespLimits::default() });
+        decoder.feed(b"*2\r\n$3\r\nGET\r\n");
+        assert_eq!(decoder.decode(), Ok(None));
+        decoder.feed(b"$3\r\nkey\r\n");
+        assert_eq!(decoder.decode(), Err(RespError { kind: RespErrorKind::FrameTooLarge { max: 16 }, offset: 16 }));
+        decoder.feed(b"*1\r\n$4\r\nPING\r\n");
+        assert_eq!(decoder.decode(), Ok(Some(argv(&["PING"]))));
+    }
+}
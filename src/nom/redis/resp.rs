@@ -1,49 +1,162 @@
-use std::fmt::{Display, Result};
+use std::fmt::{self, Display, Write};
+use std::num::IntErrorKind;
 
-use bytes::BytesMut;
+use bytes::{BufMut, Bytes, BytesMut};
 use nom::branch::alt;
-use nom::bytes::complete::{tag, take_while, take_while1};
-use nom::character::complete::{char, digit1};
+use nom::bytes::streaming::{tag, take, take_while};
+use nom::character::streaming::{char, one_of};
 use nom::combinator::{cut, map};
 use nom::IResult;
-use nom::multi::many_m_n;
-use nom::sequence::{delimited, preceded, terminated};
+use nom::sequence::{preceded, terminated};
+
+use crate::nom::redis::error::{RedisError, RespError, RespErrorKind, RespParseError};
+use crate::nom::redis::resp_ref::{self, RespRef};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Resp {
     StringLine(String),
-    Err(String),
+    Err(RedisError),
     Int(i64),
-    Batch(Option<String>),
+    Batch(Option<Bytes>),
     MultiBatch(Option<Vec<Resp>>),
-    BadReply(String),
+    // RESP3
+    Null,
+    Boolean(bool),
+    Double(f64),
+    BigNumber(String),
+    Verbatim { format: String, text: Bytes },
+    Map(Vec<(Resp, Resp)>),
+    Set(Vec<Resp>),
+    Push(Vec<Resp>),
+    Attribute { attrs: Vec<(Resp, Resp)>, reply: Box<Resp> },
 }
 
 impl Resp {
-    pub fn from_resp(src: &BytesMut) -> Self {
+    /// Decode one complete frame; the whole buffer must be consumed.
+    pub fn from_resp(src: &BytesMut) -> Result<Self, RespError> {
         debug!("{:?}", src);
-        match parse(&String::from_utf8(src.as_ref().to_vec()).unwrap()) {
-            Ok((remain, resp)) => {
-                if remain.is_empty() {
-                    resp
-                } else {
-                    Resp::BadReply(format!("remaining bytes: {}", remain))
-                }
+        match parse(src) {
+            Ok(([], resp)) => Ok(resp),
+            Ok((remain, _)) => Err(RespError {
+                kind: RespErrorKind::TrailingBytes,
+                offset: src.len() - remain.len(),
+            }),
+            Err(e) => Err(RespParseError::into_error(e, src)),
+        }
+    }
+
+    /// Name of the variant, used in conversion error messages.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Resp::StringLine(_) => "StringLine",
+            Resp::Err(_) => "Err",
+            Resp::Int(_) => "Int",
+            Resp::Batch(_) => "Batch",
+            Resp::MultiBatch(_) => "MultiBatch",
+            Resp::Null => "Null",
+            Resp::Boolean(_) => "Boolean",
+            Resp::Double(_) => "Double",
+            Resp::BigNumber(_) => "BigNumber",
+            Resp::Verbatim { .. } => "Verbatim",
+            Resp::Map(_) => "Map",
+            Resp::Set(_) => "Set",
+            Resp::Push(_) => "Push",
+            Resp::Attribute { .. } => "Attribute",
+        }
+    }
+
+    /// Serialize the value back to RESP wire bytes.
+    ///
+    /// RESP2 values are written in RESP2 form and RESP3 values in RESP3 form,
+    /// so a value parsed from the wire is written back unchanged.
+    pub fn encode(&self, buf: &mut BytesMut) {
+        match self {
+            Resp::StringLine(line) => line_to(buf, b'+', line.as_bytes()),
+            Resp::Err(err) => line_to(buf, b'-', err.to_string().as_bytes()),
+            Resp::Int(int) => header_to(buf, b':', *int),
+            Resp::Batch(None) => buf.extend_from_slice(b"$-1\r\n"),
+            Resp::Batch(Some(reply)) => bulk_to(buf, b'$', &[reply]),
+            Resp::MultiBatch(None) => buf.extend_from_slice(b"*-1\r\n"),
+            Resp::MultiBatch(Some(replies)) => {
+                header_to(buf, b'*', replies.len());
+                replies.iter().for_each(|r| r.encode(buf));
+            }
+            Resp::Null => buf.extend_from_slice(b"_\r\n"),
+            Resp::Boolean(true) => buf.extend_from_slice(b"#t\r\n"),
+            Resp::Boolean(false) => buf.extend_from_slice(b"#f\r\n"),
+            Resp::Double(d) if d.is_nan() => buf.extend_from_slice(b",nan\r\n"),
+            Resp::Double(d) => header_to(buf, b',', d),
+            Resp::BigNumber(n) => line_to(buf, b'(', n.as_bytes()),
+            Resp::Verbatim { format, text } => bulk_to(buf, b'=', &[format.as_bytes(), b":", text]),
+            Resp::Map(pairs) => {
+                header_to(buf, b'%', pairs.len());
+                pairs.iter().for_each(|(k, v)| {
+                    k.encode(buf);
+                    v.encode(buf);
+                });
+            }
+            Resp::Set(items) => {
+                header_to(buf, b'~', items.len());
+                items.iter().for_each(|r| r.encode(buf));
+            }
+            Resp::Push(items) => {
+                header_to(buf, b'>', items.len());
+                items.iter().for_each(|r| r.encode(buf));
+            }
+            Resp::Attribute { attrs, reply } => {
+                header_to(buf, b'|', attrs.len());
+                attrs.iter().for_each(|(k, v)| {
+                    k.encode(buf);
+                    v.encode(buf);
+                });
+                reply.encode(buf);
             }
-            Err(e) => Resp::BadReply(e.to_string()),
         }
     }
 }
 
+// line_to 写入 `前缀 + 内容 + \r\n`
+fn line_to(buf: &mut BytesMut, prefix: u8, line: &[u8]) {
+    buf.reserve(line.len() + 3);
+    buf.put_u8(prefix);
+    buf.extend_from_slice(line);
+    buf.extend_from_slice(b"\r\n");
+}
+
+// header_to 写入 `前缀 + 数值 + \r\n`，数值直接格式化进缓冲区
+fn header_to(buf: &mut BytesMut, prefix: u8, n: impl Display) {
+    buf.put_u8(prefix);
+    // 写入 BytesMut 不会失败
+    let _ = write!(buf, "{}\r\n", n);
+}
+
+// bulk_to 写入由多个片段拼接而成的定长内容
+fn bulk_to(buf: &mut BytesMut, prefix: u8, parts: &[&[u8]]) {
+    let len: usize = parts.iter().map(|p| p.len()).sum();
+    header_to(buf, prefix, len);
+    buf.reserve(len + 2);
+    parts.iter().for_each(|p| buf.extend_from_slice(p));
+    buf.extend_from_slice(b"\r\n");
+}
+
+// fmt_bytes 按 utf-8 输出，非法 utf-8 时按 ascii 转义输出
+fn fmt_bytes(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => write!(f, "{}", s),
+        Err(_) => write!(f, "{}", bytes.escape_ascii()),
+    }
+}
+
 impl Display for Resp {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Resp::StringLine(line) => write!(f, "+ {}", line),
             Resp::Err(err) => write!(f, "- {}", err),
             Resp::Int(int) => write!(f, ": {}", int),
             Resp::Batch(reply) => {
                 if let Some(reply) = reply {
-                    write!(f, "$ {}", reply)
+                    write!(f, "$ ")?;
+                    fmt_bytes(f, reply)
                 } else {
                     write!(f, "$-1")
                 }
@@ -64,101 +177,276 @@ impl Display for Resp {
                     write!(f, "*-1")
                 }
             }
-            Resp::BadReply(err) => write!(f, "parse reply failed: {}", err),
+            Resp::Null => write!(f, "_"),
+            Resp::Boolean(b) => write!(f, "# {}", b),
+            Resp::Double(d) => write!(f, ", {}", d),
+            Resp::BigNumber(n) => write!(f, "( {}", n),
+            Resp::Verbatim { format, text } => {
+                write!(f, "= {}:", format)?;
+                fmt_bytes(f, text)
+            }
+            Resp::Map(pairs) => {
+                write!(f, "% {}", pairs.len())?;
+                pairs.iter().try_for_each(|(k, v)| write!(f, "\r\n{}\r\n{}", k, v))
+            }
+            Resp::Set(items) => {
+                write!(f, "~ {}", items.len())?;
+                items.iter().try_for_each(|item| write!(f, "\r\n{}", item))
+            }
+            Resp::Push(items) => {
+                write!(f, "> {}", items.len())?;
+                items.iter().try_for_each(|item| write!(f, "\r\n{}", item))
+            }
+            Resp::Attribute { attrs, reply } => {
+                write!(f, "| {}", attrs.len())?;
+                attrs.iter().try_for_each(|(k, v)| write!(f, "\r\n{}\r\n{}", k, v))?;
+                write!(f, "\r\n{}", reply)
+            }
+        }
+    }
+}
+
+
+/// Result type of the RESP parsers.
+pub type RespResult<'a, O> = IResult<&'a [u8], O, RespParseError<'a>>;
+
+/// Default maximum nesting depth of aggregate replies.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// Limits applied while decoding, so that a hostile peer can not make the
+/// parser allocate or buffer without bound.
+///
+/// The defaults accept anything a Redis server sends; lower them when the
+/// other side is not trusted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RespLimits {
+    /// Maximum length of a bulk or verbatim string.
+    pub max_bulk_len: usize,
+    /// Maximum number of items declared by an aggregate, counted in pairs
+    /// for maps and attributes.
+    pub max_array_len: usize,
+    /// Maximum nesting depth of aggregates.
+    pub max_depth: usize,
+    /// Maximum size of a whole frame in bytes.
+    pub max_frame_size: usize,
+}
+
+impl Default for RespLimits {
+    fn default() -> Self {
+        RespLimits {
+            // 与 redis 的 proto-max-bulk-len 默认值一致
+            max_bulk_len: 512 * 1024 * 1024,
+            max_array_len: u32::MAX as usize,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_frame_size: 1024 * 1024 * 1024,
         }
     }
 }
 
+pub fn parse(i: &[u8]) -> RespResult<'_, Resp> {
+    parse_with_limits(i, &RespLimits::default())
+}
 
-pub fn parse(i: &str) -> IResult<&str, Resp> {
+/// Parse one reply, rejecting it as soon as it exceeds one of `limits`.
+///
+/// The reply is parsed as a [`RespRef`] and then copied into a [`Resp`].
+pub fn parse_with_limits<'a>(i: &'a [u8], limits: &RespLimits) -> RespResult<'a, Resp> {
+    owned(i, |i| resp_ref::parse_ref_with_limits(i, limits))
+}
+
+// owned 用 resp_ref 中的解析器解析，再将结果复制为 Resp
+fn owned<'a>(i: &'a [u8], parser: impl FnOnce(&'a [u8]) -> RespResult<'a, RespRef<'a>>) -> RespResult<'a, Resp> {
+    parser(i).map(|(remain, reply)| (remain, reply.to_resp()))
+}
+
+// within_frame 检查帧大小：帧已完整但超过上限，或尚未完整但已读入的内容达到上限
+pub(crate) fn within_frame<'a, O>(i: &'a [u8], limits: &RespLimits, result: RespResult<'a, O>) -> RespResult<'a, O> {
+    let max = limits.max_frame_size;
+    match result {
+        Ok((remain, _)) if i.len() - remain.len() > max => fail(&i[max..], RespErrorKind::FrameTooLarge { max }),
+        Err(nom::Err::Incomplete(_)) if i.len() >= max => fail(&i[max..], RespErrorKind::FrameTooLarge { max }),
+        result => result,
+    }
+}
+
+// parse_simple 解析所有非聚合类型
+pub fn parse_simple(i: &[u8]) -> RespResult<'_, Resp> {
+    owned(i, |i| resp_ref::simple(i, &RespLimits::default()))
+}
+
+// frame_header 读取一个元素的头部，供 decoder 按帧切分使用：
+// 简单类型整体读取并返回 0，聚合类型只读取长度行并返回其后的元素个数
+pub(crate) fn frame_header<'a>(i: &'a [u8], limits: &RespLimits) -> RespResult<'a, usize> {
     alt((
-        parse_single_line,
-        parse_err,
-        parse_int,
-        parse_batch,
-        parse_multi_batch
-    ))(i)
-}
-
-
-pub fn parse_single_line(i: &str) -> IResult<&str, Resp> {
-    preceded(char('+'), cut(terminated(
-        map(
-            take_while1(|c: char| c != '\r' && c != '\n'),
-            |resp: &str| Resp::StringLine(resp.to_string()),
-        ),
-        tag("\r\n"),
-    )))(i)
-}
-
-pub fn parse_err(i: &str) -> IResult<&str, Resp> {
-    preceded(char('-'), cut(terminated(
-        map(
-            take_while1(|c: char| c != '\r' && c != '\n'),
-            |resp: &str| Resp::Err(resp.to_string()),
-        ),
-        tag("\r\n"),
-    )))(i)
-}
-
-pub fn parse_int(i: &str) -> IResult<&str, Resp> {
-    preceded(char(':'), cut(terminated(
-        map(
-            take_while1(|c: char| c.is_digit(10) || c == '-'),
-            |resp: &str| Resp::Int(resp.parse::<i64>().unwrap()),
-        ),
-        tag("\r\n"),
-    )))(i)
-}
-
-
-pub fn parse_batch(i: &str) -> IResult<&str, Resp> {
-    preceded(char('$'), cut(alt((
-        preceded(char('-'), cut(terminated(
-            map(
-                take_while1(|c: char| c.is_digit(10)),
-                |_| Resp::Batch(None),
-            ),
-            tag("\r\n"),
-        ))),
-        preceded(preceded(digit1, tag("\r\n")), cut(terminated(
-            map(
-                take_while(|c: char| c != '\r' && c != '\n'),
-                |resp: &str| Resp::Batch(Some(resp.to_string())),
-            ),
-            tag("\r\n"),
-        )))
-    ))))(i)
-}
-
-pub fn parse_multi_batch(i: &str) -> IResult<&str, Resp> {
-    let (i, count) = delimited(
-        tag("*"),
-        map(
-            take_while1(|c: char| c.is_digit(10) || c == '-'),
-            |resp: &str| resp.parse::<i64>().unwrap(),
-        ),
-        tag("\r\n"))(i)?;
-    if count == -1 {
-        let (i, _) = tag("\r\n")(i)?;
-        return Ok((i, Resp::MultiBatch(None)));
-    }
-
-    let count = count as usize;
-    let (i, responses) = many_m_n(
-        count,
-        count,
-        alt((parse_single_line, parse_err, parse_int, parse_batch)),
-    )(i)?;
-    if responses.len() != count {
-        return Ok((
-            i,
-            Resp::BadReply(format!("expect {} items, got {}", count, responses.len())),
-        ));
-    }
-    return Ok((i, Resp::MultiBatch(Some(responses))));
+        map(preceded(one_of("+-:_#,("), cut(line)), |_| 0),
+        map(preceded(one_of("$="), cut(|i| bulk(i, limits))), |_| 0),
+        map(preceded(char('*'), cut(|i| array_len(i, limits))), |count| count.max(0) as usize),
+        map(preceded(one_of("~>"), cut(|i| count(i, limits))), |count| count),
+        map(preceded(char('%'), cut(|i| count(i, limits))), |count| count * 2),
+        // 属性之后还跟着一个真正的回复
+        map(preceded(char('|'), cut(|i| count(i, limits))), |count| count * 2 + 1),
+    ))(i).map_err(|e| match e {
+        nom::Err::Error(_) => nom::Err::Failure(RespParseError::new(i, RespErrorKind::InvalidPrefix(i[0]))),
+        e => e,
+    })
+}
+
+// fail 在 i 处产生一个不可回溯的错误
+pub(crate) fn fail<T>(i: &[u8], kind: RespErrorKind) -> RespResult<'_, T> {
+    Err(nom::Err::Failure(RespParseError::new(i, kind)))
+}
+
+// line 读取到 \r\n 为止的一行内容（不含 \r\n）
+pub(crate) fn line(i: &[u8]) -> RespResult<'_, &[u8]> {
+    terminated(take_while(|c: u8| c != b'\r' && c != b'\n'), tag("\r\n"))(i)
+}
+
+// line_value 读取一行并用 f 转换，转换失败时在行首报 kind 错误
+pub(crate) fn line_value<'a, O>(
+    kind: RespErrorKind,
+    f: impl Fn(&'a [u8]) -> Option<O>,
+) -> impl FnMut(&'a [u8]) -> RespResult<'a, O> {
+    move |i| {
+        let (remain, l) = line(i)?;
+        match f(l) {
+            Some(o) => Ok((remain, o)),
+            None => fail(i, kind.clone()),
+        }
+    }
+}
+
+// number 读取一行并解析为有符号整数
+pub(crate) fn number(i: &[u8]) -> RespResult<'_, i64> {
+    let (remain, l) = line(i)?;
+    match std::str::from_utf8(l).map(str::parse::<i64>) {
+        Ok(Ok(n)) => Ok((remain, n)),
+        Ok(Err(e)) if matches!(e.kind(), IntErrorKind::PosOverflow | IntErrorKind::NegOverflow) => {
+            fail(i, RespErrorKind::IntegerOverflow)
+        }
+        _ => fail(i, RespErrorKind::InvalidInteger),
+    }
 }
 
+// length 读取 batch 或聚合类型声明的长度，-1 表示空值
+pub(crate) fn length(i: &[u8]) -> RespResult<'_, i64> {
+    let (remain, len) = number(i).map_err(|e| e.map(|_| RespParseError::new(i, RespErrorKind::InvalidLength)))?;
+    if len < -1 {
+        return fail(i, RespErrorKind::InvalidLength);
+    }
+    Ok((remain, len))
+}
+
+// array_len 读取数组声明的元素个数，-1 表示空值，超过 max_array_len 时报错
+pub(crate) fn array_len<'a>(i: &'a [u8], limits: &RespLimits) -> RespResult<'a, i64> {
+    let (remain, len) = length(i)?;
+    if len > 0 && len as u64 > limits.max_array_len as u64 {
+        return fail(i, RespErrorKind::TooManyElements { len: len as u64, max: limits.max_array_len });
+    }
+    Ok((remain, len))
+}
+
+// count 读取聚合类型的元素个数，不允许为负数
+pub(crate) fn count<'a>(i: &'a [u8], limits: &RespLimits) -> RespResult<'a, usize> {
+    let (remain, len) = array_len(i, limits)?;
+    match usize::try_from(len) {
+        Ok(len) => Ok((remain, len)),
+        Err(_) => fail(i, RespErrorKind::InvalidLength),
+    }
+}
+
+// bulk 按声明的长度读取 batch 内容，长度为 -1 时为空值，超过 max_bulk_len 时报错
+pub(crate) fn bulk<'a>(i: &'a [u8], limits: &RespLimits) -> RespResult<'a, Option<&'a [u8]>> {
+    let (remain, len) = length(i)?;
+    if len == -1 {
+        return Ok((remain, None));
+    }
+    if len as u64 > limits.max_bulk_len as u64 {
+        return fail(i, RespErrorKind::BulkTooLong { len: len as u64, max: limits.max_bulk_len });
+    }
+    map(terminated(take(len as usize), tag("\r\n")), Some)(remain)
+}
+
+// nested 进入下一层聚合类型，超过最大深度时在聚合类型的起始位置报错
+pub(crate) fn nested(i: &[u8], depth: usize) -> Result<usize, nom::Err<RespParseError<'_>>> {
+    depth.checked_sub(1).ok_or(nom::Err::Failure(RespParseError::new(i, RespErrorKind::TooDeep)))
+}
+
+pub fn parse_single_line(i: &[u8]) -> RespResult<'_, Resp> {
+    owned(i, resp_ref::single_line)
+}
+
+// parse_err 解析错误回复，并按错误码分类
+pub fn parse_err(i: &[u8]) -> RespResult<'_, Resp> {
+    owned(i, resp_ref::err)
+}
+
+pub fn parse_int(i: &[u8]) -> RespResult<'_, Resp> {
+    owned(i, resp_ref::int)
+}
+
+
+pub fn parse_batch(i: &[u8]) -> RespResult<'_, Resp> {
+    owned(i, |i| resp_ref::batch(i, &RespLimits::default()))
+}
+
+pub fn parse_multi_batch(i: &[u8]) -> RespResult<'_, Resp> {
+    owned(i, |i| resp_ref::multi_batch(i, &RespLimits::default(), DEFAULT_MAX_DEPTH))
+}
+
+pub fn parse_null(i: &[u8]) -> RespResult<'_, Resp> {
+    owned(i, resp_ref::null)
+}
+
+pub fn parse_boolean(i: &[u8]) -> RespResult<'_, Resp> {
+    owned(i, resp_ref::boolean)
+}
+
+pub fn parse_double(i: &[u8]) -> RespResult<'_, Resp> {
+    owned(i, resp_ref::double)
+}
+
+pub fn parse_big_number(i: &[u8]) -> RespResult<'_, Resp> {
+    owned(i, resp_ref::big_number)
+}
+
+// big_number 校验大整数的内容：可选的正负号加上至少一位数字
+pub(crate) fn big_number(resp: &[u8]) -> Option<&str> {
+    let digits = resp.strip_prefix(b"-").or_else(|| resp.strip_prefix(b"+")).unwrap_or(resp);
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(resp).ok()
+}
+
+// parse_verbatim 解析 verbatim string，内容的前 4 个字节为 `格式:`，如 `txt:`
+pub fn parse_verbatim(i: &[u8]) -> RespResult<'_, Resp> {
+    owned(i, |i| resp_ref::verbatim(i, &RespLimits::default()))
+}
+
+// verbatim 将 verbatim string 的内容拆分为格式和正文
+pub(crate) fn verbatim(resp: &[u8]) -> Option<(&str, &[u8])> {
+    if resp.len() < 4 || resp[3] != b':' {
+        return None;
+    }
+    Some((std::str::from_utf8(&resp[..3]).ok()?, &resp[4..]))
+}
+
+pub fn parse_map(i: &[u8]) -> RespResult<'_, Resp> {
+    owned(i, |i| resp_ref::map_pairs(i, &RespLimits::default(), DEFAULT_MAX_DEPTH))
+}
+
+pub fn parse_set(i: &[u8]) -> RespResult<'_, Resp> {
+    owned(i, |i| resp_ref::set(i, &RespLimits::default(), DEFAULT_MAX_DEPTH))
+}
+
+pub fn parse_push(i: &[u8]) -> RespResult<'_, Resp> {
+    owned(i, |i| resp_ref::push(i, &RespLimits::default(), DEFAULT_MAX_DEPTH))
+}
+
+// parse_attribute 解析属性及其后紧跟的回复
+pub fn parse_attribute(i: &[u8]) -> RespResult<'_, Resp> {
+    owned(i, |i| resp_ref::attribute(i, &RespLimits::default(), DEFAULT_MAX_DEPTH))
+}
 
 #[cfg(test)]
 mod test {
@@ -166,7 +454,7 @@ mod test {
 
     #[test]
     fn test_parse_single_line() {
-        let (_, resp) = parse_single_line("+OK\r\n").unwrap();
+        let (_, resp) = parse_single_line(b"+OK\r\n").unwrap();
 
         if let Resp::StringLine(ok) = resp {
             assert_eq!(ok, "OK".to_string())
@@ -175,16 +463,19 @@ mod test {
 
     #[test]
     fn test_parse_err() {
-        let (_, resp) = parse_err("-Error\r\n").unwrap();
+        let (_, resp) = parse_err(b"-Error\r\n").unwrap();
 
         if let Resp::Err(err) = resp {
-            assert_eq!(err, "Error".to_string())
+            assert_eq!(err.to_string(), "Error".to_string())
         }
+
+        let (_, resp) = parse_err(b"-MOVED 3999 127.0.0.1:6381\r\n").unwrap();
+        assert_eq!(resp, Resp::Err(RedisError::Moved { slot: 3999, addr: "127.0.0.1:6381".to_string() }));
     }
 
     #[test]
     fn test_parse_int() {
-        let (_, resp) = parse_int(":-1\r\n").unwrap();
+        let (_, resp) = parse_int(b":-1\r\n").unwrap();
 
         if let Resp::Int(int) = resp {
             assert_eq!(int, -1)
@@ -193,31 +484,292 @@ mod test {
 
     #[test]
     fn test_parse_batch() {
-        let (_, none_resp) = parse_batch("$-1\r\n").unwrap();
+        let (_, none_resp) = parse_batch(b"$-1\r\n").unwrap();
         if let Resp::Batch(none) = none_resp {
             assert_eq!(none, None);
         }
-        let (_, value_resp) = parse_batch("$6\r\nfoobar\r\n").unwrap();
+        let (_, value_resp) = parse_batch(b"$6\r\nfoobar\r\n").unwrap();
         if let Resp::Batch(value) = value_resp {
-            assert_eq!(value, Some("foobar".to_string()));
+            assert_eq!(value, Some(Bytes::from("foobar")));
         }
-        let (_, value_resp) = parse_batch("$0\r\n\r\n").unwrap();
+        let (_, value_resp) = parse_batch(b"$0\r\n\r\n").unwrap();
         if let Resp::Batch(value) = value_resp {
-            assert_eq!(value, Some("".to_string()));
+            assert_eq!(value, Some(Bytes::new()));
         }
     }
 
+    #[test]
+    fn test_parse_binary_batch() {
+        let (remain, resp) = parse_batch(b"$8\r\nfoo\r\nbar\r\n").unwrap();
+        assert!(remain.is_empty());
+        assert_eq!(resp, Resp::Batch(Some(Bytes::from_static(b"foo\r\nbar"))));
+
+        let (remain, resp) = parse_batch(b"$4\r\n\x00\xff\xfe\x80\r\n").unwrap();
+        assert!(remain.is_empty());
+        assert_eq!(resp, Resp::Batch(Some(Bytes::from_static(b"\x00\xff\xfe\x80"))));
+
+        assert!(parse_batch(b"$4\r\nfoobar\r\n").is_err());
+        assert!(parse_batch(b"$-2\r\n").is_err());
+    }
+
     #[test]
     fn test_parse_multi_batch() {
-        let (_, none_resp) = parse_multi_batch("*0\r\n").unwrap();
+        let (_, none_resp) = parse_multi_batch(b"*0\r\n").unwrap();
         if let Resp::MultiBatch(responses) = none_resp {
             assert_eq!(responses.unwrap().len(), 0);
         }
 
-        let (_, value_resp) = parse_multi_batch("*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n").unwrap();
+        let (_, value_resp) = parse_multi_batch(b"*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n").unwrap();
         if let Resp::MultiBatch(responses) = value_resp {
             assert_eq!(responses.clone().unwrap().len(), 2);
-            assert_eq!(responses, Some(vec![Resp::Batch(Some("foo".to_string())), Resp::Batch(Some("bar".to_string()))]));
+            assert_eq!(responses, Some(vec![Resp::Batch(Some(Bytes::from("foo"))), Resp::Batch(Some(Bytes::from("bar")))]));
+        }
+
+        let (remain, resp) = parse_multi_batch(b"*-1\r\n").unwrap();
+        assert!(remain.is_empty());
+        assert_eq!(resp, Resp::MultiBatch(None));
+    }
+
+    #[test]
+    fn test_parse_resp3_simple() {
+        assert_eq!(parse(b"_\r\n").unwrap().1, Resp::Null);
+        assert_eq!(parse(b"#t\r\n").unwrap().1, Resp::Boolean(true));
+        assert_eq!(parse(b"#f\r\n").unwrap().1, Resp::Boolean(false));
+        assert_eq!(parse(b",1.23\r\n").unwrap().1, Resp::Double(1.23));
+        assert_eq!(parse(b",-inf\r\n").unwrap().1, Resp::Double(f64::NEG_INFINITY));
+        assert_eq!(parse(b",10\r\n").unwrap().1, Resp::Double(10.0));
+        assert!(matches!(parse(b",nan\r\n").unwrap().1, Resp::Double(d) if d.is_nan()));
+        assert_eq!(
+            parse(b"(3492890328409238509324850943850943825024385\r\n").unwrap().1,
+            Resp::BigNumber("3492890328409238509324850943850943825024385".to_string())
+        );
+        assert_eq!(
+            parse(b"=15\r\ntxt:Some string\r\n").unwrap().1,
+            Resp::Verbatim { format: "txt".to_string(), text: Bytes::from("Some string") }
+        );
+
+        assert!(parse(b"#x\r\n").is_err());
+        assert!(parse(b",abc\r\n").is_err());
+        assert!(parse(b"(12a\r\n").is_err());
+        assert!(parse(b"=3\r\ntxt\r\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_resp3_aggregate() {
+        assert_eq!(
+            parse(b"%2\r\n+first\r\n:1\r\n+second\r\n:2\r\n").unwrap().1,
+            Resp::Map(vec![
+                (Resp::StringLine("first".to_string()), Resp::Int(1)),
+                (Resp::StringLine("second".to_string()), Resp::Int(2)),
+            ])
+        );
+        assert_eq!(
+            parse(b"~3\r\n+a\r\n#t\r\n_\r\n").unwrap().1,
+            Resp::Set(vec![Resp::StringLine("a".to_string()), Resp::Boolean(true), Resp::Null])
+        );
+        assert_eq!(
+            parse(b">3\r\n$7\r\nmessage\r\n$2\r\nch\r\n$5\r\nhello\r\n").unwrap().1,
+            Resp::Push(vec![
+                Resp::Batch(Some(Bytes::from("message"))),
+                Resp::Batch(Some(Bytes::from("ch"))),
+                Resp::Batch(Some(Bytes::from("hello"))),
+            ])
+        );
+        assert_eq!(
+            parse(b"|1\r\n+key-popularity\r\n,0.19\r\n:2039\r\n").unwrap().1,
+            Resp::Attribute {
+                attrs: vec![(Resp::StringLine("key-popularity".to_string()), Resp::Double(0.19))],
+                reply: Box::new(Resp::Int(2039)),
+            }
+        );
+        assert!(matches!(parse(b"%1\r\n+a\r\n"), Err(nom::Err::Incomplete(_))));
+        assert!(parse(b"~-1\r\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_incomplete() {
+        assert!(matches!(parse(b""), Err(nom::Err::Incomplete(_))));
+        assert!(matches!(parse(b"+OK\r"), Err(nom::Err::Incomplete(_))));
+        assert!(matches!(parse(b"$6\r\nfoo"), Err(nom::Err::Incomplete(_))));
+        assert!(matches!(parse(b"*2\r\n:1\r\n"), Err(nom::Err::Incomplete(_))));
+        assert_eq!(
+            Resp::from_resp(&BytesMut::from(&b"$6\r\nfoo"[..])),
+            Err(RespError { kind: RespErrorKind::Incomplete, offset: 7 })
+        );
+    }
+
+    #[test]
+    fn test_parse_nested() {
+        // EXEC
+        let (remain, resp) = parse(b"*3\r\n+OK\r\n:2\r\n*2\r\n$1\r\na\r\n$-1\r\n").unwrap();
+        assert!(remain.is_empty());
+        assert_eq!(resp, Resp::MultiBatch(Some(vec![
+            Resp::StringLine("OK".to_string()),
+            Resp::Int(2),
+            Resp::MultiBatch(Some(vec![Resp::Batch(Some(Bytes::from("a"))), Resp::Batch(None)])),
+        ])));
+
+        // SCAN
+        let (_, resp) = parse(b"*2\r\n$2\r\n17\r\n*2\r\n$4\r\nkey1\r\n$4\r\nkey2\r\n").unwrap();
+        assert_eq!(resp, Resp::MultiBatch(Some(vec![
+            Resp::Batch(Some(Bytes::from("17"))),
+            Resp::MultiBatch(Some(vec![Resp::Batch(Some(Bytes::from("key1"))), Resp::Batch(Some(Bytes::from("key2")))])),
+        ])));
+
+        // XRANGE
+        let (_, resp) = parse(b"*1\r\n*2\r\n$3\r\n1-0\r\n*2\r\n$1\r\nf\r\n$1\r\nv\r\n").unwrap();
+        assert_eq!(resp, Resp::MultiBatch(Some(vec![Resp::MultiBatch(Some(vec![
+            Resp::Batch(Some(Bytes::from("1-0"))),
+            Resp::MultiBatch(Some(vec![Resp::Batch(Some(Bytes::from("f"))), Resp::Batch(Some(Bytes::from("v")))])),
+        ]))])));
+
+        // null array and RESP3 aggregates inside arrays
+        let (_, resp) = parse(b"*3\r\n*-1\r\n%1\r\n+k\r\n*0\r\n~1\r\n_\r\n").unwrap();
+        assert_eq!(resp, Resp::MultiBatch(Some(vec![
+            Resp::MultiBatch(None),
+            Resp::Map(vec![(Resp::StringLine("k".to_string()), Resp::MultiBatch(Some(vec![])))]),
+            Resp::Set(vec![Resp::Null]),
+        ])));
+    }
+
+    fn depth(max_depth: usize) -> RespLimits {
+        RespLimits { max_depth, ..RespLimits::default() }
+    }
+
+    #[test]
+    fn test_parse_max_depth() {
+        let src = b"*1\r\n*1\r\n*1\r\n:1\r\n";
+        assert!(parse_with_limits(src, &depth(3)).is_ok());
+        assert_eq!(
+            parse_with_limits(src, &depth(2)),
+            Err(nom::Err::Failure(RespParseError::new(&src[8..], RespErrorKind::TooDeep)))
+        );
+        assert!(parse_with_limits(b":1\r\n", &depth(0)).is_ok());
+        assert!(parse_with_limits(b"*-1\r\n", &depth(0)).is_ok());
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let limits = RespLimits { max_bulk_len: 4, max_array_len: 2, max_depth: 8, max_frame_size: 32 };
+        let cases: [(&[u8], RespErrorKind, usize); 7] = [
+            (b"$2147483647\r\n", RespErrorKind::BulkTooLong { len: 2147483647, max: 4 }, 1),
+            (b"=9\r\ntxt:hello\r\n", RespErrorKind::BulkTooLong { len: 9, max: 4 }, 1),
+            (b"*9999999999\r\n", RespErrorKind::TooManyElements { len: 9999999999, max: 2 }, 1),
+            (b"*1\r\n%3\r\n", RespErrorKind::TooManyElements { len: 3, max: 2 }, 5),
+            (b"|3\r\n", RespErrorKind::TooManyElements { len: 3, max: 2 }, 1),
+            (b"*2\r\n$4\r\nabcd\r\n*2\r\n$4\r\nabcd\r\n$4\r\n", RespErrorKind::FrameTooLarge { max: 32 }, 32),
+            (b"+0123456789012345678901234567890123456789\r\n", RespErrorKind::FrameTooLarge { max: 32 }, 32),
+        ];
+        for (src, kind, offset) in cases {
+            let err = parse_with_limits(src, &limits).map(|_| ()).map_err(|e| RespParseError::into_error(e, src));
+            assert_eq!(err, Err(RespError { kind, offset }), "{}", src.escape_ascii());
+        }
+
+        let src = b"*2\r\n$4\r\nabcd\r\n%2\r\n:1\r\n_\r\n:2\r\n_\r\n";
+        assert!(parse_with_limits(src, &limits).is_ok());
+        assert!(parse_with_limits(b"*-1\r\n", &limits).is_ok());
+    }
+
+    #[test]
+    fn test_parse_count_mismatch() {
+        let src = b"*3\r\n:1\r\n:2\r\n\r\n";
+        assert_eq!(
+            parse(src),
+            Err(nom::Err::Failure(RespParseError::new(&src[12..], RespErrorKind::CountMismatch { expected: 3, got: 2 })))
+        );
+        assert!(matches!(parse(b"*3\r\n:1\r\n:2\r\n"), Err(nom::Err::Incomplete(_))));
+        assert!(matches!(parse(b"*-2\r\n"), Err(nom::Err::Failure(_))));
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let cases: &[&[u8]] = &[
+            b"+OK\r\n",
+            b"+\r\n",
+            b"-Error\r\n",
+            b"-ERR unknown command 'FOO'\r\n",
+            b"-ASK 3999 127.0.0.1:6381\r\n",
+            b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n",
+            b":-1\r\n",
+            b":9223372036854775807\r\n",
+            b"$-1\r\n",
+            b"$6\r\nfoobar\r\n",
+            b"$0\r\n\r\n",
+            b"$8\r\nfoo\r\nbar\r\n",
+            b"$4\r\n\x00\xff\xfe\x80\r\n",
+            b"*0\r\n",
+            b"*-1\r\n",
+            b"*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n",
+            b"*2\r\n$2\r\n\xc3\x28\r\n:1\r\n",
+            b"*3\r\n+OK\r\n:2\r\n*2\r\n$1\r\na\r\n$-1\r\n",
+            b"*2\r\n$2\r\n17\r\n*2\r\n$4\r\nkey1\r\n$4\r\nkey2\r\n",
+            b"*1\r\n*2\r\n$3\r\n1-0\r\n*2\r\n$1\r\nf\r\n$1\r\nv\r\n",
+            b"*3\r\n*-1\r\n%1\r\n+k\r\n*0\r\n~1\r\n_\r\n",
+            b"_\r\n",
+            b"#t\r\n",
+            b"#f\r\n",
+            b",1.23\r\n",
+            b",-inf\r\n",
+            b",10\r\n",
+            b"(3492890328409238509324850943850943825024385\r\n",
+            b"=15\r\ntxt:Some string\r\n",
+            b"%2\r\n+first\r\n:1\r\n+second\r\n:2\r\n",
+            b"~3\r\n+a\r\n#t\r\n_\r\n",
+            b">3\r\n$7\r\nmessage\r\n$2\r\nch\r\n$5\r\nhello\r\n",
+            b"|1\r\n+key-popularity\r\n,0.19\r\n:2039\r\n",
+        ];
+        for src in cases {
+            let (_, resp) = parse(src).unwrap();
+            let mut buf = BytesMut::new();
+            resp.encode(&mut buf);
+            assert_eq!(&buf[..], *src, "encode {:?}", resp);
+            assert_eq!(parse(&buf).unwrap(), (&b""[..], resp));
         }
+
+        let mut buf = BytesMut::new();
+        Resp::Double(f64::NAN).encode(&mut buf);
+        assert!(matches!(parse(&buf).unwrap().1, Resp::Double(d) if d.is_nan()));
+    }
+
+    #[test]
+    fn test_from_resp_non_utf8() {
+        let src = BytesMut::from(&b"*2\r\n$2\r\n\xc3\x28\r\n:1\r\n"[..]);
+        assert_eq!(
+            Resp::from_resp(&src),
+            Ok(Resp::MultiBatch(Some(vec![Resp::Batch(Some(Bytes::from_static(b"\xc3\x28"))), Resp::Int(1)])))
+        );
+
+        let src = BytesMut::from(&b"+\xc3\x28\r\n"[..]);
+        assert_eq!(Resp::from_resp(&src), Err(RespError { kind: RespErrorKind::Utf8, offset: 1 }));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_from_resp_errors() {
+        let cases: &[(&[u8], RespErrorKind, usize)] = &[
+            (b"?what\r\n", RespErrorKind::InvalidPrefix(b'?'), 0),
+            (b":-\r\n", RespErrorKind::InvalidInteger, 1),
+            (b":12a\r\n", RespErrorKind::InvalidInteger, 1),
+            (b":9223372036854775808\r\n", RespErrorKind::IntegerOverflow, 1),
+            (b"$abc\r\n", RespErrorKind::InvalidLength, 1),
+            (b"$-2\r\n", RespErrorKind::InvalidLength, 1),
+            (b"*-5\r\n", RespErrorKind::InvalidLength, 1),
+            (b"%-1\r\n", RespErrorKind::InvalidLength, 1),
+            (b"$3\r\nfoobar\r\n", RespErrorKind::MissingCrlf, 7),
+            (b"+OK\n", RespErrorKind::MissingCrlf, 3),
+            (b"#x\r\n", RespErrorKind::InvalidValue, 1),
+            (b",1.2.3\r\n", RespErrorKind::InvalidValue, 1),
+            (b"=3\r\ntxt\r\n", RespErrorKind::InvalidValue, 1),
+            (b"*2\r\n:1\r\n?\r\n", RespErrorKind::CountMismatch { expected: 2, got: 1 }, 8),
+            (b"%1\r\n+k\r\n\r\n", RespErrorKind::CountMismatch { expected: 2, got: 1 }, 8),
+            (b"+OK\r\n+OK\r\n", RespErrorKind::TrailingBytes, 5),
+            (b"*1\r\n*2\r\n:1\r\n", RespErrorKind::Incomplete, 12),
+        ];
+        for (src, kind, offset) in cases {
+            assert_eq!(
+                Resp::from_resp(&BytesMut::from(*src)),
+                Err(RespError { kind: kind.clone(), offset: *offset }),
+                "decode {:?}", src.escape_ascii().to_string()
+            );
+        }
+    }
+}
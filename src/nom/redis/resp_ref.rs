@@ -0,0 +1,344 @@
+use bytes::Bytes;
+use nom::branch::alt;
+use nom::bytes::streaming::tag;
+use nom::character::streaming::char;
+use nom::combinator::{cut, map, value};
+use nom::sequence::preceded;
+
+use crate::nom::redis::error::{RedisError, RespError, RespErrorKind, RespParseError};
+use crate::nom::redis::resp::{self, Resp, RespLimits, RespResult};
+
+/// Borrowed view of a reply.
+///
+/// Lines and bulk payloads are slices of the frame buffer, so decoding only
+/// allocates the element vectors of aggregates. Use [`RespRef::to_resp`] or
+/// [`RespRef::to_resp_in`] when an owned [`Resp`] is needed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RespRef<'a> {
+    StringLine(&'a str),
+    /// The raw error line, see [`RedisError`] for the classified form.
+    Err(&'a str),
+    Int(i64),
+    Batch(Option<&'a [u8]>),
+    MultiBatch(Option<Vec<RespRef<'a>>>),
+    // RESP3
+    Null,
+    Boolean(bool),
+    Double(f64),
+    BigNumber(&'a str),
+    Verbatim { format: &'a str, text: &'a [u8] },
+    Map(Vec<(RespRef<'a>, RespRef<'a>)>),
+    Set(Vec<RespRef<'a>>),
+    Push(Vec<RespRef<'a>>),
+    Attribute { attrs: Vec<(RespRef<'a>, RespRef<'a>)>, reply: Box<RespRef<'a>> },
+}
+
+impl<'a> RespRef<'a> {
+    /// Decode one complete frame; the whole buffer must be consumed.
+    pub fn from_frame(src: &'a [u8]) -> Result<Self, RespError> {
+        match parse_ref(src) {
+            Ok(([], resp)) => Ok(resp),
+            Ok((remain, _)) => Err(RespError {
+                kind: RespErrorKind::TrailingBytes,
+                offset: src.len() - remain.len(),
+            }),
+            Err(e) => Err(RespParseError::into_error(e, src)),
+        }
+    }
+
+    /// Convert to an owned reply, copying every payload.
+    pub fn to_resp(&self) -> Resp {
+        self.owned(&|payload| Bytes::copy_from_slice(payload))
+    }
+
+    /// Convert to an owned reply whose bulk payloads share `frame` instead of
+    /// being copied. `self` must have been parsed from `frame`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a payload does not lie inside `frame`.
+    pub fn to_resp_in(&self, frame: &Bytes) -> Resp {
+        self.owned(&|payload| frame.slice_ref(payload))
+    }
+
+    // owned 递归转换为 Resp，bulk 内容由 payload 生成
+    fn owned(&self, payload: &dyn Fn(&[u8]) -> Bytes) -> Resp {
+        let all = |items: &[RespRef<'_>]| items.iter().map(|r| r.owned(payload)).collect::<Vec<_>>();
+        let pairs = |pairs: &[(RespRef<'_>, RespRef<'_>)]| {
+            pairs.iter().map(|(k, v)| (k.owned(payload), v.owned(payload))).collect::<Vec<_>>()
+        };
+        match self {
+            RespRef::StringLine(line) => Resp::StringLine(line.to_string()),
+            RespRef::Err(err) => Resp::Err(RedisError::from(*err)),
+            RespRef::Int(int) => Resp::Int(*int),
+            RespRef::Batch(reply) => Resp::Batch(reply.map(payload)),
+            RespRef::MultiBatch(replies) => Resp::MultiBatch(replies.as_deref().map(all)),
+            RespRef::Null => Resp::Null,
+            RespRef::Boolean(b) => Resp::Boolean(*b),
+            RespRef::Double(d) => Resp::Double(*d),
+            RespRef::BigNumber(n) => Resp::BigNumber(n.to_string()),
+            RespRef::Verbatim { format, text } => Resp::Verbatim { format: format.to_string(), text: payload(text) },
+            RespRef::Map(items) => Resp::Map(pairs(items)),
+            RespRef::Set(items) => Resp::Set(all(items)),
+            RespRef::Push(items) => Resp::Push(all(items)),
+            RespRef::Attribute { attrs, reply } => Resp::Attribute {
+                attrs: pairs(attrs),
+                reply: Box::new(reply.owned(payload)),
+            },
+        }
+    }
+}
+
+
+pub fn parse_ref(i: &[u8]) -> RespResult<'_, RespRef<'_>> {
+    parse_ref_with_limits(i, &RespLimits::default())
+}
+
+/// Parse one reply without copying it, rejecting it as soon as it exceeds
+/// one of `limits`. [`resp::parse_with_limits`] is built on this parser.
+pub fn parse_ref_with_limits<'a>(i: &'a [u8], limits: &RespLimits) -> RespResult<'a, RespRef<'a>> {
+    resp::within_frame(i, limits, value_with_depth(i, limits, limits.max_depth))
+}
+
+// value_with_depth 解析一个回复，其中的聚合类型最多还能嵌套 depth 层
+fn value_with_depth<'a>(i: &'a [u8], limits: &RespLimits, depth: usize) -> RespResult<'a, RespRef<'a>> {
+    alt((
+        |i| simple(i, limits),
+        |i| multi_batch(i, limits, depth),
+        |i| map_pairs(i, limits, depth),
+        |i| set(i, limits, depth),
+        |i| push(i, limits, depth),
+        |i| attribute(i, limits, depth),
+    ))(i).map_err(|e| match e {
+        // 所有类型都不匹配，说明首字节不是合法的类型前缀
+        nom::Err::Error(_) => nom::Err::Error(RespParseError::new(i, RespErrorKind::InvalidPrefix(i[0]))),
+        e => e,
+    })
+}
+
+// simple 解析所有非聚合类型
+pub(crate) fn simple<'a>(i: &'a [u8], limits: &RespLimits) -> RespResult<'a, RespRef<'a>> {
+    alt((
+        single_line,
+        err,
+        int,
+        |i| batch(i, limits),
+        null,
+        boolean,
+        double,
+        big_number,
+        |i| verbatim(i, limits),
+    ))(i)
+}
+
+fn utf8(line: &[u8]) -> Option<&str> {
+    std::str::from_utf8(line).ok()
+}
+
+pub(crate) fn single_line(i: &[u8]) -> RespResult<'_, RespRef<'_>> {
+    preceded(char('+'), cut(resp::line_value(RespErrorKind::Utf8, |l| utf8(l).map(RespRef::StringLine))))(i)
+}
+
+pub(crate) fn err(i: &[u8]) -> RespResult<'_, RespRef<'_>> {
+    preceded(char('-'), cut(resp::line_value(RespErrorKind::Utf8, |l| utf8(l).map(RespRef::Err))))(i)
+}
+
+pub(crate) fn int(i: &[u8]) -> RespResult<'_, RespRef<'_>> {
+    preceded(char(':'), cut(map(resp::number, RespRef::Int)))(i)
+}
+
+pub(crate) fn batch<'a>(i: &'a [u8], limits: &RespLimits) -> RespResult<'a, RespRef<'a>> {
+    preceded(char('$'), cut(map(|i| resp::bulk(i, limits), RespRef::Batch)))(i)
+}
+
+pub(crate) fn null(i: &[u8]) -> RespResult<'_, RespRef<'_>> {
+    preceded(char('_'), cut(value(RespRef::Null, tag("\r\n"))))(i)
+}
+
+pub(crate) fn boolean(i: &[u8]) -> RespResult<'_, RespRef<'_>> {
+    preceded(char('#'), cut(resp::line_value(RespErrorKind::InvalidValue, |l| match l {
+        b"t" => Some(RespRef::Boolean(true)),
+        b"f" => Some(RespRef::Boolean(false)),
+        _ => None,
+    })))(i)
+}
+
+pub(crate) fn double(i: &[u8]) -> RespResult<'_, RespRef<'_>> {
+    preceded(char(','), cut(resp::line_value(RespErrorKind::InvalidValue, |l| {
+        utf8(l)?.parse::<f64>().ok().map(RespRef::Double)
+    })))(i)
+}
+
+pub(crate) fn big_number(i: &[u8]) -> RespResult<'_, RespRef<'_>> {
+    preceded(char('('), cut(resp::line_value(RespErrorKind::InvalidValue, |l| {
+        resp::big_number(l).map(RespRef::BigNumber)
+    })))(i)
+}
+
+// verbatim 内容的前 4 个字节为 `格式:`，如 `txt:`
+pub(crate) fn verbatim<'a>(i: &'a [u8], limits: &RespLimits) -> RespResult<'a, RespRef<'a>> {
+    let (remain, reply) = preceded(char('='), cut(|i| resp::bulk(i, limits)))(i)?;
+    match reply.and_then(resp::verbatim) {
+        Some((format, text)) => Ok((remain, RespRef::Verbatim { format, text })),
+        None => resp::fail(&i[1..], RespErrorKind::InvalidValue),
+    }
+}
+
+pub(crate) fn multi_batch<'a>(i: &'a [u8], limits: &RespLimits, depth: usize) -> RespResult<'a, RespRef<'a>> {
+    let (remain, count) = preceded(char('*'), cut(|i| resp::array_len(i, limits)))(i)?;
+    if count == -1 {
+        return Ok((remain, RespRef::MultiBatch(None)));
+    }
+    let (remain, items) = elements(remain, limits, count as usize, resp::nested(i, depth)?)?;
+    Ok((remain, RespRef::MultiBatch(Some(items))))
+}
+
+pub(crate) fn map_pairs<'a>(i: &'a [u8], limits: &RespLimits, depth: usize) -> RespResult<'a, RespRef<'a>> {
+    let (remain, count) = preceded(char('%'), cut(|i| resp::count(i, limits)))(i)?;
+    let (remain, items) = pairs(remain, limits, count, resp::nested(i, depth)?)?;
+    Ok((remain, RespRef::Map(items)))
+}
+
+pub(crate) fn set<'a>(i: &'a [u8], limits: &RespLimits, depth: usize) -> RespResult<'a, RespRef<'a>> {
+    let (remain, count) = preceded(char('~'), cut(|i| resp::count(i, limits)))(i)?;
+    let (remain, items) = elements(remain, limits, count, resp::nested(i, depth)?)?;
+    Ok((remain, RespRef::Set(items)))
+}
+
+pub(crate) fn push<'a>(i: &'a [u8], limits: &RespLimits, depth: usize) -> RespResult<'a, RespRef<'a>> {
+    let (remain, count) = preceded(char('>'), cut(|i| resp::count(i, limits)))(i)?;
+    let (remain, items) = elements(remain, limits, count, resp::nested(i, depth)?)?;
+    Ok((remain, RespRef::Push(items)))
+}
+
+// attribute 解析属性及其后紧跟的回复
+pub(crate) fn attribute<'a>(i: &'a [u8], limits: &RespLimits, depth: usize) -> RespResult<'a, RespRef<'a>> {
+    let (remain, count) = preceded(char('|'), cut(|i| resp::count(i, limits)))(i)?;
+    let depth = resp::nested(i, depth)?;
+    let (remain, attrs) = pairs(remain, limits, count, depth)?;
+    let (remain, reply) = element(remain, limits, depth, count * 2 + 1, count * 2)?;
+    Ok((remain, RespRef::Attribute { attrs, reply: Box::new(reply) }))
+}
+
+// element 读取聚合类型中的第 got 个元素，此处不是合法元素说明元素个数与声明不符
+fn element<'a>(
+    i: &'a [u8],
+    limits: &RespLimits,
+    depth: usize,
+    expected: usize,
+    got: usize,
+) -> RespResult<'a, RespRef<'a>> {
+    value_with_depth(i, limits, depth).map_err(|e| match e {
+        nom::Err::Error(_) => nom::Err::Failure(RespParseError::new(i, RespErrorKind::CountMismatch { expected, got })),
+        e => e,
+    })
+}
+
+// elements 逐个读取 count 个元素
+fn elements<'a>(mut i: &'a [u8], limits: &RespLimits, count: usize, depth: usize) -> RespResult<'a, Vec<RespRef<'a>>> {
+    let mut items = Vec::with_capacity(count.min(i.len() / 3));
+    for got in 0..count {
+        let (remain, item) = element(i, limits, depth, count, got)?;
+        items.push(item);
+        i = remain;
+    }
+    Ok((i, items))
+}
+
+// pairs 逐个读取 count 个键值对，元素个数按键和值分别计算
+fn pairs<'a>(
+    mut i: &'a [u8],
+    limits: &RespLimits,
+    count: usize,
+    depth: usize,
+) -> RespResult<'a, Vec<(RespRef<'a>, RespRef<'a>)>> {
+    let mut items = Vec::new();
+    for got in 0..count {
+        let (remain, key) = element(i, limits, depth, count * 2, got * 2)?;
+        let (remain, value) = element(remain, limits, depth, count * 2, got * 2 + 1)?;
+        items.push((key, value));
+        i = remain;
+    }
+    Ok((i, items))
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::nom::redis::resp_ref::*;
+
+    #[test]
+    fn test_parse_ref() {
+        let src = b"*3\r\n$3\r\nfoo\r\n$-1\r\n%1\r\n+k\r\n=8\r\ntxt:a\r\nb\r\n";
+        let (remain, reply) = parse_ref(src).unwrap();
+        assert!(remain.is_empty());
+        assert_eq!(reply, RespRef::MultiBatch(Some(vec![
+            RespRef::Batch(Some(b"foo")),
+            RespRef::Batch(None),
+            RespRef::Map(vec![(RespRef::StringLine("k"), RespRef::Verbatim { format: "txt", text: b"a\r\nb" })]),
+        ])));
+        if let RespRef::MultiBatch(Some(items)) = &reply {
+            if let RespRef::Batch(Some(foo)) = items[0] {
+                assert_eq!(foo.as_ptr(), src[8..].as_ptr());
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_resp() {
+        let cases: [&[u8]; 6] = [
+            b"+OK\r\n",
+            b"-MOVED 3999 127.0.0.1:6381\r\n",
+            b"*2\r\n:1\r\n*-1\r\n",
+            b"|1\r\n+ttl\r\n:3600\r\n~2\r\n#t\r\n,1.5\r\n",
+            b">2\r\n(123\r\n_\r\n",
+            b"=8\r\ntxt:a\r\nb\r\n",
+        ];
+        for src in cases {
+            let frame = Bytes::copy_from_slice(src);
+            let reply = RespRef::from_frame(&frame).unwrap();
+            let (_, owned) = resp::parse(src).unwrap();
+            assert_eq!(reply.to_resp(), owned);
+            assert_eq!(reply.to_resp_in(&frame), owned);
+        }
+    }
+
+    #[test]
+    fn test_to_resp_in_shares_frame() {
+        let frame = Bytes::from_static(b"*1\r\n$5\r\nhello\r\n");
+        match RespRef::from_frame(&frame).unwrap().to_resp_in(&frame) {
+            Resp::MultiBatch(Some(items)) => match &items[0] {
+                Resp::Batch(Some(hello)) => assert_eq!(hello.as_ptr(), frame[8..].as_ptr()),
+                other => panic!("unexpected reply: {:?}", other),
+            },
+            other => panic!("unexpected reply: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_ref_errors() {
+        let cases: [(&[u8], RespErrorKind, usize); 6] = [
+            (b"?x\r\n", RespErrorKind::InvalidPrefix(b'?'), 0),
+            (b"*2\r\n:1\r\n", RespErrorKind::Incomplete, 8),
+            (b"*2\r\n:1\r\n?\r\n", RespErrorKind::CountMismatch { expected: 2, got: 1 }, 8),
+            (b"$3\r\nab\r\n", RespErrorKind::MissingCrlf, 7),
+            (b"(12a\r\n", RespErrorKind::InvalidValue, 1),
+            (b"+OK\r\n:1\r\n", RespErrorKind::TrailingBytes, 5),
+        ];
+        for (src, kind, offset) in cases {
+            assert_eq!(RespRef::from_frame(src), Err(RespError { kind, offset }), "{:?}", src.escape_ascii().to_string());
+        }
+
+        let limits = RespLimits { max_depth: 2, max_bulk_len: 3, ..RespLimits::default() };
+        let (_, deep) = parse_ref_with_limits(b"*1\r\n*1\r\n:1\r\n", &limits).unwrap();
+        assert_eq!(deep, RespRef::MultiBatch(Some(vec![RespRef::MultiBatch(Some(vec![RespRef::Int(1)]))])));
+        assert_eq!(
+            parse_ref_with_limits(b"*1\r\n*1\r\n*1\r\n", &limits),
+            Err(nom::Err::Failure(RespParseError::new(b"*1\r\n", RespErrorKind::TooDeep)))
+        );
+        assert_eq!(
+            parse_ref_with_limits(b"$4\r\n", &limits),
+            Err(nom::Err::Failure(RespParseError::new(b"4\r\n", RespErrorKind::BulkTooLong { len: 4, max: 3 })))
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::nom::redis::command::{CmdBuilder, ToArg};
+use crate::nom::redis::connection::Connection;
+use crate::nom::redis::error::RedisError;
+use crate::nom::redis::resp::Resp;
+
+/// Lua script whose SHA1 is computed locally, so that it is normally sent
+/// with EVALSHA and only transferred in full when the server does not have
+/// it cached yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    code: String,
+    hash: String,
+}
+
+impl Script {
+    pub fn new(code: impl Into<String>) -> Self {
+        let code = code.into();
+        let hash = sha1_smol::Sha1::from(&code).digest().to_string();
+        Script { code, hash }
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// Lower case hex SHA1 of the script, as used by EVALSHA.
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// Run the script with EVALSHA, falling back to EVAL on `NOSCRIPT`.
+    ///
+    /// `keys` become `KEYS[1..]` and `args` become `ARGV[1..]` in the script.
+    pub async fn invoke<S, K, A>(&self, conn: &mut Connection<S>, keys: &[K], args: &[A]) -> io::Result<Resp>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        K: ToArg,
+        A: ToArg,
+    {
+        conn.write_all(&self.eval_bytes("EVALSHA", &self.hash, keys, args)).await?;
+        match conn.read_reply().await? {
+            Resp::Err(RedisError::NoScript(_)) => {
+                debug!("script {} is not cached, sending it with EVAL", self.hash);
+                conn.write_all(&self.eval_bytes("EVAL", &self.code, keys, args)).await?;
+                conn.read_reply().await
+            }
+            reply => Ok(reply),
+        }
+    }
+
+    /// Cache the script on the server with SCRIPT LOAD; the reply is the
+    /// SHA1 computed by the server.
+    pub async fn load<S: AsyncRead + AsyncWrite + Unpin>(&self, conn: &mut Connection<S>) -> io::Result<Resp> {
+        conn.write_all(&CmdBuilder::new().arg("SCRIPT").arg("LOAD").arg(self.code.as_str()).to_bytes()).await?;
+        conn.read_reply().await
+    }
+
+    // eval_bytes 编码 `EVAL|EVALSHA script numkeys key [key ...] arg [arg ...]`
+    fn eval_bytes<K: ToArg, A: ToArg>(&self, command: &str, script: &str, keys: &[K], args: &[A]) -> bytes::BytesMut {
+        let mut builder = CmdBuilder::new().arg(command).arg(script).arg(keys.len());
+        keys.iter().for_each(|key| builder.add_arg(key));
+        args.iter().for_each(|arg| builder.add_arg(arg));
+        builder.to_bytes()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::nom::redis::script::*;
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(Script::new("return 1").hash(), "e0e1f9fabfc9d4800c877a703b823ac0578ff8db");
+        assert_eq!(Script::new("").hash(), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+    }
+
+    #[tokio::test]
+    async fn test_invoke_fallback() {
+        let script = Script::new("return redis.call('INCRBY', KEYS[1], ARGV[1])");
+        let evalsha = format!(
+            "*5\r\n$7\r\nEVALSHA\r\n$40\r\n{}\r\n$1\r\n1\r\n$4\r\nrate\r\n$1\r\n5\r\n",
+            script.hash()
+        );
+        let eval = format!(
+            "*5\r\n$4\r\nEVAL\r\n${}\r\n{}\r\n$1\r\n1\r\n$4\r\nrate\r\n$1\r\n5\r\n",
+            script.code().len(),
+            script.code()
+        );
+
+        let (client, mut server) = tokio::io::duplex(1024);
+        let script_server = tokio::spawn(async move {
+            for (request, reply) in [
+                (&evalsha, &b"-NOSCRIPT No matching script. Please use EVAL.\r\n"[..]),
+                (&eval, b":5\r\n"),
+                (&evalsha, b":10\r\n"),
+            ] {
+                let mut buf = vec![0; request.len()];
+                server.read_exact(&mut buf).await.unwrap();
+                assert_eq!(String::from_utf8(buf).unwrap(), *request);
+                server.write_all(reply).await.unwrap();
+            }
+        });
+
+        let mut conn = Connection::new(client);
+        assert_eq!(script.invoke(&mut conn, &["rate"], &["5"]).await.unwrap(), Resp::Int(5));
+        assert_eq!(script.invoke(&mut conn, &[b"rate"], &[5]).await.unwrap(), Resp::Int(10));
+        script_server.await.unwrap();
+    }
+}
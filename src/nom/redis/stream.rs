@@ -0,0 +1,148 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use bytes::Bytes;
+
+use crate::nom::redis::from_resp::{FromResp, FromRespError};
+use crate::nom::redis::resp::Resp;
+
+/// ID of a stream entry, `<milliseconds>-<sequence>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl FromStr for StreamId {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{}' is not a valid stream id", s);
+        let (ms, seq) = s.split_once('-').ok_or_else(invalid)?;
+        Ok(StreamId {
+            ms: ms.parse().map_err(|_| invalid())?,
+            seq: seq.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+impl FromResp for StreamId {
+    fn from_resp(resp: Resp) -> Result<Self, FromRespError> {
+        String::from_resp(resp)?
+            .parse()
+            .map_err(|_| FromRespError::Unexpected { expected: "stream id", got: "malformed id" })
+    }
+}
+
+/// Entry of a stream, as returned by XRANGE, XREAD and XREADGROUP.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamEntry {
+    pub id: StreamId,
+    /// Field value pairs in insertion order. Empty for an entry that was
+    /// deleted while still pending in a consumer group.
+    pub fields: Vec<(Bytes, Bytes)>,
+}
+
+impl FromResp for StreamEntry {
+    // [id, [field, value, ...]]，已删除的待确认条目字段为 nil
+    fn from_resp(resp: Resp) -> Result<Self, FromRespError> {
+        let (id, fields) = <(StreamId, Option<Vec<Bytes>>)>::from_resp(resp)?;
+        let fields = fields.unwrap_or_default();
+        if fields.len() % 2 != 0 {
+            return Err(FromRespError::Unexpected { expected: "stream entry fields", got: "MultiBatch of odd length" });
+        }
+        let mut fields = fields.into_iter();
+        let mut pairs = Vec::with_capacity(fields.len() / 2);
+        while let (Some(field), Some(value)) = (fields.next(), fields.next()) {
+            pairs.push((field, value));
+        }
+        Ok(StreamEntry { id, fields: pairs })
+    }
+}
+
+/// Entries read from one stream by XREAD or XREADGROUP.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamEntries {
+    pub key: Bytes,
+    pub entries: Vec<StreamEntry>,
+}
+
+impl FromResp for StreamEntries {
+    fn from_resp(resp: Resp) -> Result<Self, FromRespError> {
+        let (key, entries) = FromResp::from_resp(resp)?;
+        Ok(StreamEntries { key, entries })
+    }
+}
+
+/// Decode the reply of XREAD or XREADGROUP, in the order of the requested
+/// keys. A blocking read that timed out gives no streams.
+pub fn read_streams(resp: Resp) -> Result<Vec<StreamEntries>, FromRespError> {
+    match resp {
+        // RESP3 中以 key 到条目列表的 map 返回
+        Resp::Map(pairs) => pairs
+            .into_iter()
+            .map(|(key, entries)| Ok(StreamEntries { key: FromResp::from_resp(key)?, entries: FromResp::from_resp(entries)? }))
+            .collect(),
+        resp => FromResp::from_resp(resp),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::nom::redis::resp::parse;
+    use crate::nom::redis::stream::*;
+
+    fn bytes(s: &str) -> Bytes {
+        Bytes::from(s.to_string())
+    }
+
+    #[test]
+    fn test_stream_id() {
+        assert_eq!("1700000000000-3".parse(), Ok(StreamId { ms: 1700000000000, seq: 3 }));
+        assert_eq!(StreamId { ms: 5, seq: 0 }.to_string(), "5-0");
+        assert!("1700000000000".parse::<StreamId>().is_err());
+        assert!("a-1".parse::<StreamId>().is_err());
+    }
+
+    #[test]
+    fn test_xrange() {
+        let src = b"*2\r\n*2\r\n$3\r\n1-0\r\n*4\r\n$4\r\ntype\r\n$5\r\nlogin\r\n$4\r\nuser\r\n$1\r\na\r\n*2\r\n$3\r\n2-0\r\n*-1\r\n";
+        let (_, resp) = parse(src).unwrap();
+        assert_eq!(Vec::<StreamEntry>::from_resp(resp), Ok(vec![
+            StreamEntry {
+                id: StreamId { ms: 1, seq: 0 },
+                fields: vec![(bytes("type"), bytes("login")), (bytes("user"), bytes("a"))],
+            },
+            StreamEntry { id: StreamId { ms: 2, seq: 0 }, fields: vec![] },
+        ]));
+
+        let (_, resp) = parse(b"*2\r\n$3\r\n1-0\r\n*1\r\n$4\r\ntype\r\n").unwrap();
+        assert!(StreamEntry::from_resp(resp).is_err());
+    }
+
+    #[test]
+    fn test_read_streams() {
+        let entry = StreamEntry { id: StreamId { ms: 1, seq: 1 }, fields: vec![(bytes("n"), bytes("1"))] };
+        let expected = vec![
+            StreamEntries { key: bytes("a"), entries: vec![entry.clone()] },
+            StreamEntries { key: bytes("b"), entries: vec![] },
+        ];
+
+        let resp2 = b"*2\r\n*2\r\n$1\r\na\r\n*1\r\n*2\r\n$3\r\n1-1\r\n*2\r\n$1\r\nn\r\n$1\r\n1\r\n*2\r\n$1\r\nb\r\n*0\r\n";
+        let resp3 = b"%2\r\n$1\r\na\r\n*1\r\n*2\r\n$3\r\n1-1\r\n*2\r\n$1\r\nn\r\n$1\r\n1\r\n$1\r\nb\r\n*0\r\n";
+        for src in [&resp2[..], &resp3[..]] {
+            let (_, resp) = parse(src).unwrap();
+            assert_eq!(read_streams(resp), Ok(expected.clone()));
+        }
+
+        // BLOCK 超时
+        assert_eq!(read_streams(Resp::MultiBatch(None)), Ok(vec![]));
+        assert_eq!(read_streams(Resp::Null), Ok(vec![]));
+    }
+}
@@ -0,0 +1,310 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::nom::redis::command::{CmdBuilder, ToArg};
+use crate::nom::redis::connection::{Connection, Pipeline};
+use crate::nom::redis::error::RedisError;
+use crate::nom::redis::resp::Resp;
+
+/// Outcome of a MULTI / EXEC block.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Exec {
+    /// The transaction ran. There is one reply per queued command, and a
+    /// command that failed while running is a [`Resp::Err`] at its position.
+    Committed(Vec<Resp>),
+    /// A watched key was modified before EXEC, so nothing ran.
+    Aborted,
+}
+
+/// Error of a transaction that could not be run.
+#[derive(Debug)]
+pub enum TransactionError {
+    Io(io::Error),
+    /// The server refused the command at `index` while queueing it, so the
+    /// whole transaction was discarded.
+    Queue { index: usize, error: RedisError },
+    /// WATCH, MULTI or EXEC itself failed.
+    Server(RedisError),
+    /// A reply that does not belong to the transaction protocol.
+    Unexpected(Resp),
+}
+
+impl Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionError::Io(e) => write!(f, "{}", e),
+            TransactionError::Queue { index, error } => write!(f, "command {} was not queued: {}", index, error),
+            TransactionError::Server(error) => write!(f, "server error: {}", error),
+            TransactionError::Unexpected(resp) => write!(f, "unexpected reply in transaction: {}", resp.kind()),
+        }
+    }
+}
+
+impl Error for TransactionError {}
+
+impl From<io::Error> for TransactionError {
+    fn from(e: io::Error) -> Self {
+        TransactionError::Io(e)
+    }
+}
+
+// expect 检查 WATCH / MULTI / 入队命令的状态回复
+fn expect(resp: Resp, status: &str) -> Result<(), Resp> {
+    match resp {
+        Resp::StringLine(line) if line == status => Ok(()),
+        resp => Err(resp),
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    /// WATCH `keys`, so that the next EXEC on this connection aborts if any
+    /// of them is modified in the meantime.
+    pub async fn watch<K: ToArg>(&mut self, keys: &[K]) -> Result<(), TransactionError> {
+        let mut builder = CmdBuilder::new().arg("WATCH");
+        keys.iter().for_each(|key| builder.add_arg(key));
+        self.write_all(&builder.to_bytes()).await?;
+        match expect(self.read_reply().await?, "OK") {
+            Ok(()) => Ok(()),
+            Err(Resp::Err(error)) => Err(TransactionError::Server(error)),
+            Err(resp) => Err(TransactionError::Unexpected(resp)),
+        }
+    }
+
+    /// Run `commands` in a MULTI / EXEC block.
+    ///
+    /// MULTI, the commands and EXEC are sent in one write. Keys watched
+    /// with [`Connection::watch`] are released by EXEC.
+    pub async fn exec(&mut self, commands: &Pipeline) -> Result<Exec, TransactionError> {
+        let mut tx = Pipeline::new();
+        tx.add_argv(&["MULTI"]).extend(commands).add_argv(&["EXEC"]);
+        let mut replies = self.pipeline(&tx).await?.into_iter();
+
+        // 回复依次为 MULTI、每条命令的 QUEUED、EXEC；MULTI 失败时命令已在事务之外执行
+        match expect(replies.next().unwrap_or(Resp::Null), "OK") {
+            Ok(()) => {}
+            Err(Resp::Err(error)) => return Err(TransactionError::Server(error)),
+            Err(resp) => return Err(TransactionError::Unexpected(resp)),
+        }
+        let mut rejected = None;
+        for (index, queued) in replies.by_ref().take(commands.len()).enumerate() {
+            match expect(queued, "QUEUED") {
+                Ok(()) => {}
+                Err(Resp::Err(error)) => {
+                    rejected.get_or_insert(TransactionError::Queue { index, error });
+                }
+                Err(resp) => return Err(TransactionError::Unexpected(resp)),
+            }
+        }
+
+        match replies.next().unwrap_or(Resp::Null) {
+            Resp::MultiBatch(Some(results)) => Ok(Exec::Committed(results)),
+            Resp::MultiBatch(None) | Resp::Null => Ok(Exec::Aborted),
+            // EXECABORT 时报告第一条被拒绝的命令
+            Resp::Err(error) => Err(rejected.unwrap_or(TransactionError::Server(error))),
+            resp => Err(TransactionError::Unexpected(resp)),
+        }
+    }
+
+    /// Optimistic transaction: WATCH `keys`, let `build` read what it needs
+    /// and return the commands to run, then EXEC them. When a watched key
+    /// changes before EXEC, everything is retried from the WATCH, at most
+    /// `max_attempts` times in total.
+    ///
+    /// Returns [`Exec::Aborted`] when every attempt was aborted. An error
+    /// returned by `build` is passed through after the keys are unwatched.
+    pub async fn transaction<K, F, E>(&mut self, keys: &[K], max_attempts: usize, mut build: F) -> Result<Exec, E>
+    where
+        K: ToArg,
+        F: AsyncFnMut(&mut Self) -> Result<Pipeline, E>,
+        E: From<io::Error> + From<TransactionError>,
+    {
+        for attempt in 1..=max_attempts {
+            self.watch(keys).await?;
+            let commands = match build(self).await {
+                Ok(commands) => commands,
+                Err(e) => {
+                    // 放弃本次事务，避免后续命令仍受 WATCH 影响
+                    self.write_all(&CmdBuilder::new().arg("UNWATCH").to_bytes()).await?;
+                    self.read_reply().await?;
+                    return Err(e);
+                }
+            };
+            match self.exec(&commands).await? {
+                Exec::Committed(results) => return Ok(Exec::Committed(results)),
+                Exec::Aborted => debug!("watched keys changed, attempt {} of {}", attempt, max_attempts),
+            }
+        }
+        Ok(Exec::Aborted)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    use crate::nom::redis::command::Commands;
+    use crate::nom::redis::from_resp::FromResp;
+    use crate::nom::redis::transaction::*;
+
+    // server 按顺序读取期望的请求并写回对应的回复
+    fn server(mut stream: DuplexStream, script: Vec<(&'static [u8], &'static [u8])>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            for (request, reply) in script {
+                let mut buf = vec![0; request.len()];
+                stream.read_exact(&mut buf).await.unwrap();
+                assert_eq!(buf.escape_ascii().to_string(), request.escape_ascii().to_string());
+                stream.write_all(reply).await.unwrap();
+            }
+        })
+    }
+
+    const MULTI: &[u8] = b"*1\r\n$5\r\nMULTI\r\n";
+    const EXEC: &[u8] = b"*1\r\n$4\r\nEXEC\r\n";
+
+    #[tokio::test]
+    async fn test_exec() {
+        let (client, stream) = tokio::io::duplex(1024);
+        let server = server(stream, vec![
+            (
+                b"*1\r\n$5\r\nMULTI\r\n*2\r\n$4\r\nINCR\r\n$1\r\nk\r\n*3\r\n$5\r\nLPUSH\r\n$1\r\nk\r\n$1\r\nv\r\n*1\r\n$4\r\nEXEC\r\n",
+                b"+OK\r\n+QUEUED\r\n+QUEUED\r\n*2\r\n:1\r\n-WRONGTYPE Operation against a key\r\n",
+            ),
+            (b"*2\r\n$5\r\nWATCH\r\n$1\r\nk\r\n", b"+OK\r\n"),
+            (
+                b"*1\r\n$5\r\nMULTI\r\n*2\r\n$4\r\nINCR\r\n$1\r\nk\r\n*1\r\n$4\r\nEXEC\r\n",
+                b"+OK\r\n+QUEUED\r\n*-1\r\n",
+            ),
+            (
+                b"*1\r\n$5\r\nMULTI\r\n*2\r\n$4\r\nINCR\r\n$1\r\nk\r\n*1\r\n$4\r\nINCR\r\n*1\r\n$4\r\nEXEC\r\n",
+                b"+OK\r\n+QUEUED\r\n-ERR wrong number of arguments for 'incr' command\r\n-EXECABORT Transaction discarded because of previous errors.\r\n",
+            ),
+            (
+                b"*1\r\n$5\r\nMULTI\r\n*2\r\n$4\r\nINCR\r\n$1\r\nk\r\n*1\r\n$4\r\nEXEC\r\n",
+                b"-ERR MULTI calls can not be nested\r\n:2\r\n-ERR EXEC without MULTI\r\n",
+            ),
+        ]);
+
+        let mut conn = Connection::new(client);
+        let mut commands = Pipeline::new();
+        commands.add(&Commands::Incr { key: "k".to_string() }).add_argv(&["LPUSH", "k", "v"]);
+        assert_eq!(conn.exec(&commands).await.unwrap(), Exec::Committed(vec![
+            Resp::Int(1),
+            Resp::Err(RedisError::WrongType("Operation against a key".to_string())),
+        ]));
+
+        let mut commands = Pipeline::new();
+        commands.add(&Commands::Incr { key: "k".to_string() });
+        conn.watch(&[b"k"]).await.unwrap();
+        assert_eq!(conn.exec(&commands).await.unwrap(), Exec::Aborted);
+
+        commands.add_argv(&["INCR"]);
+        match conn.exec(&commands).await {
+            Err(TransactionError::Queue { index: 1, error }) => assert_eq!(error.code(), "ERR"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // MULTI 失败时报告服务端的错误，而不是 INCR 的回复
+        let mut commands = Pipeline::new();
+        commands.add(&Commands::Incr { key: "k".to_string() });
+        match conn.exec(&commands).await {
+            Err(TransactionError::Server(error)) => assert_eq!(error.to_string(), "ERR MULTI calls can not be nested"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_transaction_retry() {
+        let watch: &[u8] = b"*2\r\n$5\r\nWATCH\r\n$1\r\nk\r\n";
+        let get: &[u8] = b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n";
+        let (client, stream) = tokio::io::duplex(1024);
+        let server = server(stream, vec![
+            (watch, b"+OK\r\n"),
+            (get, b"$1\r\n1\r\n"),
+            (MULTI, b""),
+            (b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\n2\r\n", b""),
+            (EXEC, b"+OK\r\n+QUEUED\r\n*-1\r\n"),
+            (watch, b"+OK\r\n"),
+            (get, b"$1\r\n5\r\n"),
+            (MULTI, b""),
+            (b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\n6\r\n", b""),
+            (EXEC, b"+OK\r\n+QUEUED\r\n*1\r\n+OK\r\n"),
+        ]);
+
+        let mut conn = Connection::new(client);
+        let mut attempts = 0;
+        let result: Result<Exec, TransactionError> = conn.transaction(&["k"], 3, async |conn| {
+            attempts += 1;
+            let value = i64::from_resp(conn.request(&Commands::Get { key: "k".to_string() }).await?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let mut commands = Pipeline::new();
+            commands.add_argv(&["SET", "k", &(value + 1).to_string()]);
+            Ok(commands)
+        }).await;
+
+        assert_eq!(result.unwrap(), Exec::Committed(vec![Resp::StringLine("OK".to_string())]));
+        assert_eq!(attempts, 2);
+        server.await.unwrap();
+    }
+
+    #[derive(Debug)]
+    enum BuildError {
+        Transaction(TransactionError),
+        Empty,
+    }
+
+    impl From<io::Error> for BuildError {
+        fn from(e: io::Error) -> Self {
+            BuildError::Transaction(e.into())
+        }
+    }
+
+    impl From<TransactionError> for BuildError {
+        fn from(e: TransactionError) -> Self {
+            BuildError::Transaction(e)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transaction_limits() {
+        let watch: &[u8] = b"*2\r\n$5\r\nWATCH\r\n$1\r\nk\r\n";
+        let incr: &[u8] = b"*1\r\n$5\r\nMULTI\r\n*2\r\n$4\r\nINCR\r\n$1\r\nk\r\n*1\r\n$4\r\nEXEC\r\n";
+        let (client, stream) = tokio::io::duplex(1024);
+        let server = server(stream, vec![
+            (watch, b"+OK\r\n"),
+            (incr, b"+OK\r\n+QUEUED\r\n*-1\r\n"),
+            (watch, b"+OK\r\n"),
+            (incr, b"+OK\r\n+QUEUED\r\n*-1\r\n"),
+            (watch, b"+OK\r\n"),
+            (b"*1\r\n$7\r\nUNWATCH\r\n", b"+OK\r\n"),
+            (watch, b"-ERR WATCH inside MULTI is not allowed\r\n"),
+        ]);
+
+        // 每次都被打断，两次之后放弃
+        let mut conn = Connection::new(client);
+        let result = conn.transaction(&["k"], 2, async |_| {
+            let mut commands = Pipeline::new();
+            commands.add(&Commands::Incr { key: "k".to_string() });
+            Ok::<_, BuildError>(commands)
+        }).await;
+        assert_eq!(result.unwrap(), Exec::Aborted);
+
+        // build 的错误原样返回
+        let result = conn.transaction(&["k"], 2, async |_| Err::<Pipeline, _>(BuildError::Empty)).await;
+        assert!(matches!(result, Err(BuildError::Empty)));
+
+        // WATCH 的错误经 From<TransactionError> 转换
+        let result = conn.transaction(&["k"], 2, async |_| Err::<Pipeline, _>(BuildError::Empty)).await;
+        match result {
+            Err(BuildError::Transaction(TransactionError::Server(error))) => {
+                assert_eq!(error.to_string(), "ERR WATCH inside MULTI is not allowed")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        server.await.unwrap();
+    }
+}